@@ -0,0 +1,17 @@
+use std::{env, process};
+
+use snek::{config::ProgramConfig, server};
+
+/**
+ * The dedicated server. Takes the same arguments as the game, with `--port` to listen on and
+ * `--players` to wait for before each match.
+ */
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let cfg = ProgramConfig::build(&args).unwrap_or_else(|err| {
+        println!("A problem occured when parsing args: {err}");
+        process::exit(1);
+    });
+
+    server::run(&cfg);
+}
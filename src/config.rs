@@ -1,24 +1,191 @@
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+
 use bevy::prelude::Resource;
+use bevy_turborand::{DelegatedRng, GlobalRng};
+
+use crate::{
+    game::{
+        ai::AiSlots, brain::BrainRegistry, external::DEFAULT_BOT_BUDGET, grid::ArenaMode,
+        protocol::DEFAULT_SERVER_PORT,
+    },
+    netplay::NetRole,
+};
 
 #[derive(Resource)]
 pub struct Debug(pub bool);
-#[derive(Default)]
+
+/**
+ * The seed every match starts its random number generator from. Two matches with the same seed,
+ * arena and inputs play out exactly the same.
+ */
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct Seed(pub u64);
+
+impl Seed {
+    /// The seed of the match after this one, so every match of a session still follows from the
+    /// first seed without two of them playing out the same.
+    pub fn next(self) -> Seed {
+        // one step of a 64 bit linear congruential generator
+        Seed(
+            self.0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407),
+        )
+    }
+}
+
 pub struct ProgramConfig {
     pub debug: bool,
+    pub arena_mode: ArenaMode,
+    pub level: usize,
+    pub headless: bool,
+    pub ticks: u32,
+    pub players: usize,
+    pub seed: u64,
+    pub replay: Option<PathBuf>,
+    pub net: Option<NetRole>,
+    /// The dedicated server to play on.
+    pub connect: Option<SocketAddr>,
+    pub name: String,
+    /// The port the dedicated server listens on.
+    pub port: u16,
+    /// Which player slots are steered by an AI.
+    pub ai: AiSlots,
+    /// The bots to play against each other headless.
+    pub tournament: Option<Vec<String>>,
+    /// Matches per pairing in a tournament.
+    pub rounds: u32,
+    pub list_bots: bool,
+    /// How long external bots get to answer each tick.
+    pub bot_budget: Duration,
+}
+
+impl Default for ProgramConfig {
+    fn default() -> Self {
+        ProgramConfig {
+            debug: false,
+            arena_mode: ArenaMode::default(),
+            level: 0,
+            headless: false,
+            ticks: 1000,
+            players: 2,
+            // a fresh seed per launch unless one is given with --seed
+            seed: GlobalRng::new().u64(..),
+            replay: None,
+            net: None,
+            connect: None,
+            name: "Player".to_string(),
+            port: DEFAULT_SERVER_PORT,
+            ai: AiSlots::default(),
+            tournament: None,
+            rounds: 10,
+            list_bots: false,
+            bot_budget: DEFAULT_BOT_BUDGET,
+        }
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(
+    value: Option<&String>,
+    err: &'static str,
+) -> Result<T, &'static str> {
+    value.and_then(|v| v.parse().ok()).ok_or(err)
 }
 
 impl ProgramConfig {
+    /// The bots that can be picked with `--ai` and `--tournament`.
+    pub fn brain_registry(&self) -> BrainRegistry {
+        let mut registry = BrainRegistry::default();
+        registry.bot_budget = self.bot_budget;
+        registry
+    }
+
     pub fn build(args: &[String]) -> Result<ProgramConfig, &'static str> {
         let mut cfg = ProgramConfig::default();
         if args.len() == 0 {
             return Ok(cfg);
         }
 
-        for arg in args {
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
             match arg.as_ref() {
                 "-d" | "--debug" => {
                     cfg.debug = true;
                 }
+                "-w" | "--wrap" => {
+                    cfg.arena_mode = ArenaMode::Wrap;
+                }
+                "-l" | "--level" => {
+                    cfg.level = parse_value(args.next(), "--level needs an arena number")?;
+                }
+                "--headless" => {
+                    cfg.headless = true;
+                }
+                "--ticks" => {
+                    cfg.ticks = parse_value(args.next(), "--ticks needs a number")?;
+                }
+                "--players" => {
+                    cfg.players = parse_value(args.next(), "--players needs a number")?;
+                }
+                "--seed" => {
+                    cfg.seed = parse_value(args.next(), "--seed needs a number")?;
+                }
+                "--replay" => {
+                    let path = args.next().ok_or("--replay needs a replay file")?;
+                    cfg.replay = Some(PathBuf::from(path));
+                }
+                "--host" => {
+                    let port = parse_value(args.next(), "--host needs a port")?;
+                    cfg.net = Some(NetRole::Host(port));
+                }
+                "--join" => {
+                    let addr =
+                        parse_value(args.next(), "--join needs an address like 127.0.0.1:7777")?;
+                    cfg.net = Some(NetRole::Join(addr));
+                }
+                "--connect" => {
+                    cfg.connect = Some(parse_value(
+                        args.next(),
+                        "--connect needs an address like 127.0.0.1:7777",
+                    )?);
+                }
+                "--name" => {
+                    cfg.name = parse_value(args.next(), "--name needs a name")?;
+                }
+                "--port" => {
+                    cfg.port = parse_value(args.next(), "--port needs a port")?;
+                }
+                "--ai" => {
+                    let list = args
+                        .next()
+                        .ok_or("--ai needs a list of players like human,greedy")?;
+                    cfg.ai = AiSlots::parse(list, &BrainRegistry::default())?;
+                }
+                "--tournament" => {
+                    let list = args
+                        .next()
+                        .ok_or("--tournament needs a list of bots like greedy,floodfill")?;
+                    let registry = BrainRegistry::default();
+                    let bots: Vec<String> =
+                        list.split(',').map(|bot| bot.trim().to_string()).collect();
+                    if bots.len() < 2 {
+                        return Err("--tournament needs at least two bots");
+                    }
+                    if !bots.iter().all(|bot| registry.contains(bot)) {
+                        return Err("unknown bot, see --list-bots");
+                    }
+                    cfg.tournament = Some(bots);
+                }
+                "--rounds" => {
+                    cfg.rounds = parse_value(args.next(), "--rounds needs a number")?;
+                }
+                "--bot-budget" => {
+                    let millis = parse_value(args.next(), "--bot-budget needs milliseconds")?;
+                    cfg.bot_budget = Duration::from_millis(millis);
+                }
+                "--list-bots" => {
+                    cfg.list_bots = true;
+                }
                 _ => return Err("unknown argument"),
             }
         }
@@ -0,0 +1,470 @@
+use std::{fs, io, path::PathBuf};
+
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    game::prelude::{ControlScheme, SnakeDirection},
+    menu::{MENU_SELECTED_COLOR, MENU_TEXT_COLOR},
+};
+
+pub const KEY_SET_COUNT: usize = 4;
+/// The folder the game keeps its files in, in the user's config and data directories.
+pub const CONFIG_DIR: &str = "snek-snaks";
+const BINDINGS_FILE: &str = "controls.json";
+/// Opens and closes the controls screen.
+pub const CONTROLS_KEY: KeyCode = KeyCode::F9;
+/// Keys that already do something else and can't be bound.
+const RESERVED_KEYS: [KeyCode; 12] = [
+    KeyCode::Escape,
+    KeyCode::Space,
+    KeyCode::Tab,
+    KeyCode::Delete,
+    KeyCode::KeyL,
+    KeyCode::KeyP,
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    CONTROLS_KEY,
+    KeyCode::F12,
+];
+/// Keys the menus and this screen are worked with. Only the binding they have by default may
+/// take them back, so the menus never steer a snake nobody meant them to.
+const MENU_KEYS: [KeyCode; 5] = [
+    KeyCode::ArrowUp,
+    KeyCode::ArrowDown,
+    KeyCode::ArrowLeft,
+    KeyCode::ArrowRight,
+    KeyCode::Enter,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    Up,
+    Down,
+    Left,
+    Right,
+    Action,
+}
+
+const BINDINGS: [Binding; 5] = [
+    Binding::Up,
+    Binding::Down,
+    Binding::Left,
+    Binding::Right,
+    Binding::Action,
+];
+
+impl Binding {
+    fn label(&self) -> &'static str {
+        match self {
+            Binding::Up => "UP",
+            Binding::Down => "DOWN",
+            Binding::Left => "LEFT",
+            Binding::Right => "RIGHT",
+            Binding::Action => "ACTION",
+        }
+    }
+}
+
+/// A short name for a key: `KeyW` is `W`, `ArrowUp` is `UP`.
+pub fn key_label(key: KeyCode) -> String {
+    let label = format!("{key:?}");
+    let label = ["Key", "Digit", "Arrow"]
+        .iter()
+        .find_map(|prefix| label.strip_prefix(prefix))
+        .unwrap_or(&label);
+    label.to_uppercase()
+}
+
+/**
+ * The keys of one player: four to steer and one to confirm with.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeySet {
+    pub up: KeyCode,
+    pub down: KeyCode,
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub action: KeyCode,
+}
+
+impl KeySet {
+    pub fn direction(&self, key: KeyCode) -> Option<SnakeDirection> {
+        match key {
+            key if key == self.up => Some(SnakeDirection::Up),
+            key if key == self.down => Some(SnakeDirection::Down),
+            key if key == self.left => Some(SnakeDirection::Left),
+            key if key == self.right => Some(SnakeDirection::Right),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, key: KeyCode) -> bool {
+        key == self.action || self.direction(key).is_some()
+    }
+
+    pub fn key(&self, binding: Binding) -> KeyCode {
+        match binding {
+            Binding::Up => self.up,
+            Binding::Down => self.down,
+            Binding::Left => self.left,
+            Binding::Right => self.right,
+            Binding::Action => self.action,
+        }
+    }
+
+    fn key_mut(&mut self, binding: Binding) -> &mut KeyCode {
+        match binding {
+            Binding::Up => &mut self.up,
+            Binding::Down => &mut self.down,
+            Binding::Left => &mut self.left,
+            Binding::Right => &mut self.right,
+            Binding::Action => &mut self.action,
+        }
+    }
+
+    /// `WASD` style when every direction is a single letter, otherwise `default`.
+    pub fn label(&self, default: &str) -> String {
+        let letters: Vec<String> = [self.up, self.left, self.down, self.right]
+            .into_iter()
+            .map(key_label)
+            .collect();
+        if letters == ["UP", "LEFT", "DOWN", "RIGHT"] {
+            "ARROWS".to_string()
+        } else if letters.iter().all(|letter| letter.chars().count() == 1) {
+            letters.concat()
+        } else {
+            default.to_string()
+        }
+    }
+
+    pub fn control_scheme(&self) -> ControlScheme {
+        let mut directional_controls = HashMap::new();
+        directional_controls.insert(self.up, SnakeDirection::Up);
+        directional_controls.insert(self.down, SnakeDirection::Down);
+        directional_controls.insert(self.left, SnakeDirection::Left);
+        directional_controls.insert(self.right, SnakeDirection::Right);
+        ControlScheme {
+            directional_controls,
+        }
+    }
+}
+
+/**
+ * The key set of every player slot, kept in `controls.json` in the user's config directory.
+ */
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyBindings {
+    pub sets: [KeySet; KEY_SET_COUNT],
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            sets: [
+                KeySet {
+                    up: KeyCode::ArrowUp,
+                    down: KeyCode::ArrowDown,
+                    left: KeyCode::ArrowLeft,
+                    right: KeyCode::ArrowRight,
+                    action: KeyCode::Enter,
+                },
+                KeySet {
+                    up: KeyCode::KeyW,
+                    down: KeyCode::KeyS,
+                    left: KeyCode::KeyA,
+                    right: KeyCode::KeyD,
+                    action: KeyCode::KeyE,
+                },
+                KeySet {
+                    up: KeyCode::KeyT,
+                    down: KeyCode::KeyG,
+                    left: KeyCode::KeyF,
+                    right: KeyCode::KeyH,
+                    action: KeyCode::KeyY,
+                },
+                KeySet {
+                    up: KeyCode::Numpad8,
+                    down: KeyCode::Numpad5,
+                    left: KeyCode::Numpad4,
+                    right: KeyCode::Numpad6,
+                    action: KeyCode::Numpad0,
+                },
+            ],
+        }
+    }
+}
+
+impl KeyBindings {
+    fn path() -> Option<PathBuf> {
+        Some(dirs::config_dir()?.join(CONFIG_DIR).join(BINDINGS_FILE))
+    }
+
+    /// The saved bindings, or the defaults if there are none or they can't be read.
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return KeyBindings::default();
+        };
+        let bindings = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).map_err(io::Error::from),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(KeyBindings::default()),
+            Err(err) => Err(err),
+        };
+        bindings.unwrap_or_else(|err| {
+            warn!("could not load controls from {}: {err}", path.display());
+            KeyBindings::default()
+        })
+    }
+
+    pub fn save(&self) -> io::Result<PathBuf> {
+        let path = Self::path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+
+    /// The key set and binding `key` is bound to.
+    fn owner(&self, key: KeyCode) -> Option<(usize, Binding)> {
+        self.sets.iter().enumerate().find_map(|(set, keys)| {
+            BINDINGS
+                .iter()
+                .find(|binding| keys.key(**binding) == key)
+                .map(|binding| (set, *binding))
+        })
+    }
+
+    /// The key set that `key` belongs to.
+    pub fn set_of(&self, key: KeyCode) -> Option<usize> {
+        self.sets.iter().position(|set| set.contains(key))
+    }
+}
+
+/// Whether the controls screen is showing, on top of whatever else is.
+#[derive(States, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ControlsScreen {
+    #[default]
+    Closed,
+    Open,
+}
+
+#[derive(Resource, Default)]
+struct ControlsCursor {
+    set: usize,
+    binding: usize,
+    /// Waiting for the key to bind to the selected cell.
+    capturing: bool,
+    message: String,
+}
+
+#[derive(Component)]
+struct BindingCell {
+    set: usize,
+    binding: usize,
+}
+
+#[derive(Component)]
+struct ControlsMessage;
+
+pub fn controls_key_pressed(keyboard: Res<ButtonInput<KeyCode>>) -> bool {
+    keyboard.just_pressed(CONTROLS_KEY)
+}
+
+pub fn open_controls_screen(mut next_state: ResMut<NextState<ControlsScreen>>) {
+    next_state.set(ControlsScreen::Open);
+}
+
+fn save_bindings(bindings: &KeyBindings) -> String {
+    match bindings.save() {
+        Ok(path) => {
+            info!("controls saved to {}", path.display());
+            "SAVED".to_string()
+        }
+        Err(err) => {
+            warn!("could not save controls: {err}");
+            "COULD NOT SAVE".to_string()
+        }
+    }
+}
+
+/**
+ * The arrow keys pick a binding, enter rebinds it to the next key pressed and delete puts back
+ * the defaults. A key that is already taken by another player, by the game or by the menus is
+ * refused.
+ */
+fn controls_keys(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut cursor: ResMut<ControlsCursor>,
+    mut bindings: ResMut<KeyBindings>,
+    mut next_state: ResMut<NextState<ControlsScreen>>,
+) {
+    let Some(key) = keyboard.get_just_pressed().next().copied() else {
+        return;
+    };
+    let binding = BINDINGS[cursor.binding];
+
+    if cursor.capturing {
+        cursor.capturing = false;
+        let current = (cursor.set, binding);
+        cursor.message = match bindings.owner(key) {
+            _ if key == KeyCode::Escape => String::new(),
+            _ if RESERVED_KEYS.contains(&key) => format!("{} IS USED BY THE GAME", key_label(key)),
+            _ if MENU_KEYS.contains(&key)
+                && KeyBindings::default().sets[cursor.set].key(binding) != key =>
+            {
+                format!("{} IS USED BY THE MENUS", key_label(key))
+            }
+            Some(owner) if owner != current => format!(
+                "{} IS ALREADY P{} {}",
+                key_label(key),
+                owner.0 + 1,
+                owner.1.label()
+            ),
+            _ => {
+                *bindings.sets[cursor.set].key_mut(binding) = key;
+                save_bindings(&bindings)
+            }
+        };
+        return;
+    }
+
+    match key {
+        KeyCode::ArrowUp => cursor.binding = (cursor.binding + BINDINGS.len() - 1) % BINDINGS.len(),
+        KeyCode::ArrowDown => cursor.binding = (cursor.binding + 1) % BINDINGS.len(),
+        KeyCode::ArrowLeft => cursor.set = (cursor.set + KEY_SET_COUNT - 1) % KEY_SET_COUNT,
+        KeyCode::ArrowRight => cursor.set = (cursor.set + 1) % KEY_SET_COUNT,
+        KeyCode::Enter => {
+            cursor.capturing = true;
+            cursor.message = format!("PRESS A KEY FOR P{} {}", cursor.set + 1, binding.label());
+        }
+        KeyCode::Delete => {
+            *bindings = KeyBindings::default();
+            cursor.message = save_bindings(&bindings);
+        }
+        KeyCode::Escape | CONTROLS_KEY => next_state.set(ControlsScreen::Closed),
+        _ => {}
+    }
+}
+
+fn spawn_controls_screen(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/visitor.ttf");
+    let text_style = |font_size: f32| TextStyle {
+        font_size,
+        color: Color::WHITE,
+        font: font.clone(),
+    };
+    commands.insert_resource(ControlsCursor::default());
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(16.),
+                    ..default()
+                },
+                background_color: Color::BLACK.with_alpha(0.9).into(),
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            StateScoped(ControlsScreen::Open),
+        ))
+        .with_children(|builder| {
+            builder.spawn(TextBundle::from_section("CONTROLS", text_style(50.)));
+            builder
+                .spawn(NodeBundle {
+                    style: Style {
+                        display: Display::Grid,
+                        grid_template_columns: RepeatedGridTrack::px(
+                            KEY_SET_COUNT as u16 + 1,
+                            140.,
+                        ),
+                        row_gap: Val::Px(8.),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|grid| {
+                    grid.spawn(TextBundle::from_section("", text_style(30.)));
+                    for set in 0..KEY_SET_COUNT {
+                        grid.spawn(TextBundle::from_section(
+                            format!("P{}", set + 1),
+                            text_style(30.),
+                        ));
+                    }
+                    for (binding, name) in BINDINGS.iter().enumerate() {
+                        grid.spawn(TextBundle::from_section(name.label(), text_style(30.)));
+                        for set in 0..KEY_SET_COUNT {
+                            grid.spawn((
+                                TextBundle::from_section("", text_style(30.)),
+                                BindingCell { set, binding },
+                            ));
+                        }
+                    }
+                });
+            builder.spawn((
+                TextBundle::from_section("", text_style(30.)),
+                ControlsMessage,
+            ));
+            builder.spawn(TextBundle::from_section(
+                "ARROWS: PICK  ENTER: REBIND  DELETE: DEFAULTS  ESC: BACK",
+                text_style(20.),
+            ));
+        });
+}
+
+fn update_controls_screen(
+    bindings: Res<KeyBindings>,
+    cursor: Res<ControlsCursor>,
+    mut cells: Query<(&mut Text, &BindingCell), Without<ControlsMessage>>,
+    mut messages: Query<&mut Text, With<ControlsMessage>>,
+) {
+    if !bindings.is_changed() && !cursor.is_changed() {
+        return;
+    }
+    for (mut text, cell) in cells.iter_mut() {
+        let selected = cell.set == cursor.set && cell.binding == cursor.binding;
+        let section = &mut text.sections[0];
+        section.value = match (selected, cursor.capturing) {
+            (true, true) => "...".to_string(),
+            _ => key_label(bindings.sets[cell.set].key(BINDINGS[cell.binding])),
+        };
+        section.style.color = if selected {
+            MENU_SELECTED_COLOR
+        } else {
+            MENU_TEXT_COLOR
+        };
+    }
+    for mut text in messages.iter_mut() {
+        text.sections[0].value.clone_from(&cursor.message);
+    }
+}
+
+/**
+ * Rebinding of the players' keys, saved between runs. The screen is opened with
+ * `open_controls_screen` from any menu.
+ */
+pub struct ControlsPlugin;
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_state::<ControlsScreen>()
+            .enable_state_scoped_entities::<ControlsScreen>()
+            .insert_resource(KeyBindings::load())
+            .add_systems(OnEnter(ControlsScreen::Open), spawn_controls_screen)
+            .add_systems(
+                Update,
+                (controls_keys, update_controls_screen)
+                    .chain()
+                    .run_if(in_state(ControlsScreen::Open)),
+            );
+    }
+}
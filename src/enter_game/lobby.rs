@@ -0,0 +1,531 @@
+use bevy::{
+    input::keyboard::{Key, KeyboardInput},
+    prelude::*,
+    utils::HashMap,
+};
+
+use crate::{
+    controls::{key_label, KeyBindings, KEY_SET_COUNT},
+    game::{
+        ai::{AiController, AiSlots},
+        brain::BrainRegistry,
+        prelude::{
+            ControlScheme, DirectionQueue, Named, Player, PlayerBundle, Score, SnakeDirection,
+            SnakeHeadRef, SnakeTextureIndex,
+        },
+        sprites::{GameSprites, SKIN_COUNT},
+    },
+    gamepad::{GamepadControls, GamepadDirections},
+    GameState,
+};
+
+pub const MAX_SLOTS: usize = KEY_SET_COUNT;
+const MAX_NAME_LENGTH: usize = 12;
+/// The keys that start editing the name of the player in the same slot.
+const NAME_KEYS: [KeyCode; MAX_SLOTS] = [KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4];
+
+/// What joined a lobby slot, and changes its settings and steers its snake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlotInput {
+    /// An index into `KeyBindings::sets`.
+    Keys(usize),
+    Gamepad(Gamepad),
+}
+
+/// The lobby slot a player was spawned from. Online, the host always sits in the first.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LobbySeat(pub usize);
+
+#[derive(Debug, Clone)]
+pub struct LobbySlot {
+    pub input: SlotInput,
+    pub name: String,
+    pub skin: usize,
+    /// The bot steering this snake, `None` for the player at the keys.
+    pub bot: Option<String>,
+    pub ready: bool,
+}
+
+/**
+ * The players signed up for the next match. Slots stay between matches, only the ready
+ * flags are cleared every time the lobby is entered.
+ */
+#[derive(Resource, Debug, Default)]
+pub struct Lobby {
+    pub slots: Vec<LobbySlot>,
+    /// The slot whose name is being typed.
+    pub editing: Option<usize>,
+}
+
+impl Lobby {
+    /// A lobby with the first `count` slots already taken, by the bots in `ai` or by humans.
+    pub fn with_players(count: usize, ai: &AiSlots) -> Self {
+        Lobby {
+            slots: (0..count.min(MAX_SLOTS))
+                .map(|i| LobbySlot {
+                    input: SlotInput::Keys(i),
+                    name: format!("Player {}", i + 1),
+                    skin: i % SKIN_COUNT,
+                    bot: ai.get(i).map(str::to_string),
+                    ready: false,
+                })
+                .collect(),
+            editing: None,
+        }
+    }
+
+    fn slot_with_input(&self, input: SlotInput) -> Option<usize> {
+        self.slots.iter().position(|slot| slot.input == input)
+    }
+
+    fn skin_taken(&self, skin: usize, except: usize) -> bool {
+        self.slots
+            .iter()
+            .enumerate()
+            .any(|(i, slot)| i != except && slot.skin == skin)
+    }
+
+    /// The next skin from `skin` in steps of `step` that nobody else wears, if any.
+    fn next_skin(&self, slot: usize, step: usize) -> usize {
+        let skin = self.slots[slot].skin;
+        (1..SKIN_COUNT)
+            .map(|i| (skin + i * step) % SKIN_COUNT)
+            .find(|skin| !self.skin_taken(*skin, slot))
+            .unwrap_or(skin)
+    }
+
+    fn join(&mut self, input: SlotInput) {
+        let skin = (0..SKIN_COUNT)
+            .find(|skin| !self.skin_taken(*skin, usize::MAX))
+            .unwrap_or(0);
+        self.slots.push(LobbySlot {
+            input,
+            name: format!("Player {}", self.slots.len() + 1),
+            skin,
+            bot: None,
+            ready: false,
+        });
+    }
+
+    /**
+     * Left and right change the skin of a slot, up and down switch between a human and the bots in
+     * `controllers`. Nothing changes once the slot is ready.
+     */
+    fn steer(&mut self, i: usize, direction: SnakeDirection, controllers: &[Option<String>]) {
+        if self.slots[i].ready {
+            return;
+        }
+        match direction {
+            SnakeDirection::Left => self.slots[i].skin = self.next_skin(i, SKIN_COUNT - 1),
+            SnakeDirection::Right => self.slots[i].skin = self.next_skin(i, 1),
+            SnakeDirection::Up | SnakeDirection::Down => {
+                let current = controllers
+                    .iter()
+                    .position(|bot| *bot == self.slots[i].bot)
+                    .unwrap_or(0);
+                let step = if direction == SnakeDirection::Up {
+                    controllers.len() - 1
+                } else {
+                    1
+                };
+                self.slots[i].bot = controllers[(current + step) % controllers.len()].clone();
+            }
+        }
+    }
+
+    pub fn all_ready(&self) -> bool {
+        !self.slots.is_empty() && self.slots.iter().all(|slot| slot.ready)
+    }
+}
+
+/// Clears the ready flags and any unfinished name of the previous visit.
+pub fn reset_lobby(mut lobby: ResMut<Lobby>) {
+    lobby.editing = None;
+    for slot in lobby.slots.iter_mut() {
+        slot.ready = false;
+    }
+}
+
+/// Starts the match again, straight from `GameState::LeaveGame`.
+pub fn rematch(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::InGame);
+}
+
+pub fn not_editing_name(lobby: Res<Lobby>) -> bool {
+    lobby.editing.is_none()
+}
+
+/// A human first, then every registered bot.
+fn controllers(registry: &BrainRegistry) -> Vec<Option<String>> {
+    std::iter::once(None)
+        .chain(registry.names().map(|name| Some(name.to_string())))
+        .collect()
+}
+
+/**
+ * Any key of a free key set joins a new slot. In a slot, the direction keys change its settings
+ * and the action key readies up.
+ */
+pub fn lobby_keys(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    registry: Res<BrainRegistry>,
+    mut lobby: ResMut<Lobby>,
+) {
+    if lobby.editing.is_some() {
+        return;
+    }
+    let controllers = controllers(&registry);
+
+    for key in keyboard.get_just_pressed() {
+        let Some(keys) = bindings.set_of(*key) else {
+            continue;
+        };
+        let Some(i) = lobby.slot_with_input(SlotInput::Keys(keys)) else {
+            if lobby.slots.len() < MAX_SLOTS {
+                lobby.join(SlotInput::Keys(keys));
+            }
+            continue;
+        };
+
+        let key_set = &bindings.sets[keys];
+        if *key == key_set.action {
+            lobby.slots[i].ready = !lobby.slots[i].ready;
+        } else if let Some(direction) = key_set.direction(*key) {
+            lobby.steer(i, direction, &controllers);
+        }
+    }
+}
+
+/**
+ * Every connected gamepad gets a slot of its own, for as long as it stays connected. The D-pad
+ * or stick change the slot's settings and the south button readies up.
+ */
+pub fn lobby_gamepads(
+    gamepads: Res<Gamepads>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+    directions: Res<GamepadDirections>,
+    registry: Res<BrainRegistry>,
+    mut lobby: ResMut<Lobby>,
+) {
+    let disconnected = |slot: &LobbySlot| match slot.input {
+        SlotInput::Gamepad(gamepad) => !gamepads.contains(gamepad),
+        SlotInput::Keys(_) => false,
+    };
+    if lobby.slots.iter().any(disconnected) {
+        lobby.slots.retain(|slot| !disconnected(slot));
+        lobby.editing = None;
+    }
+    for gamepad in gamepads.iter() {
+        let input = SlotInput::Gamepad(gamepad);
+        if lobby.slot_with_input(input).is_none() && lobby.slots.len() < MAX_SLOTS {
+            lobby.join(input);
+        }
+    }
+
+    let controllers = controllers(&registry);
+    for (gamepad, direction) in directions.just_pressed.iter() {
+        if let Some(i) = lobby.slot_with_input(SlotInput::Gamepad(*gamepad)) {
+            lobby.steer(i, direction.clone(), &controllers);
+        }
+    }
+    for button in buttons.get_just_pressed() {
+        if button.button_type != GamepadButtonType::South {
+            continue;
+        }
+        if let Some(i) = lobby.slot_with_input(SlotInput::Gamepad(button.gamepad)) {
+            lobby.slots[i].ready = !lobby.slots[i].ready;
+        }
+    }
+}
+
+/// The match starts once everyone in the lobby is ready.
+pub fn start_when_ready(lobby: Res<Lobby>, mut next_state: ResMut<NextState<GameState>>) {
+    if lobby.is_changed() && lobby.all_ready() {
+        next_state.set(GameState::InGame);
+    }
+}
+
+/**
+ * F1 to F4 rename the player in that slot: typing replaces the name, enter or escape is done.
+ */
+pub fn name_entry(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut typed: EventReader<KeyboardInput>,
+    mut lobby: ResMut<Lobby>,
+) {
+    if let Some(slot) = NAME_KEYS.iter().position(|key| keyboard.just_pressed(*key)) {
+        if slot < lobby.slots.len() && lobby.editing != Some(slot) {
+            lobby.editing = Some(slot);
+            lobby.slots[slot].name.clear();
+        } else {
+            lobby.editing = None;
+        }
+        typed.clear();
+        return;
+    }
+
+    let Some(slot) = lobby.editing else {
+        typed.clear();
+        return;
+    };
+    for event in typed.read() {
+        if !event.state.is_pressed() {
+            continue;
+        }
+        let name = &mut lobby.slots[slot].name;
+        match &event.logical_key {
+            Key::Character(text) => {
+                for c in text.chars().filter(|c| !c.is_control()) {
+                    if name.chars().count() < MAX_NAME_LENGTH {
+                        name.push(c);
+                    }
+                }
+            }
+            Key::Space if name.chars().count() < MAX_NAME_LENGTH => name.push(' '),
+            Key::Backspace => {
+                name.pop();
+            }
+            Key::Enter | Key::Escape => {
+                if name.trim().is_empty() {
+                    *name = format!("Player {}", slot + 1);
+                }
+                lobby.editing = None;
+                break;
+            }
+            _ => {}
+        }
+    }
+}
+
+/**
+ * Puts a `Player` in the match for every slot of the lobby.
+ */
+pub fn spawn_lobby_players(
+    mut commands: Commands,
+    lobby: Res<Lobby>,
+    bindings: Res<KeyBindings>,
+    registry: Res<BrainRegistry>,
+) {
+    for (seat, slot) in lobby.slots.iter().enumerate() {
+        let controller = slot
+            .bot
+            .as_deref()
+            .and_then(|bot| AiController::from_registry(&registry, bot));
+        let control_scheme = match (&controller, slot.input) {
+            (None, SlotInput::Keys(keys)) => bindings.sets[keys].control_scheme(),
+            _ => ControlScheme {
+                directional_controls: HashMap::new(),
+            },
+        };
+        let mut player = commands.spawn(PlayerBundle {
+            player: Player,
+            name: Named(slot.name.clone()),
+            control_scheme,
+            direction_queue: DirectionQueue::default(),
+            snake_head_ref: SnakeHeadRef(None),
+            score: Score { value: 0 },
+            texture_index: SnakeTextureIndex(slot.skin),
+        });
+        player.insert(LobbySeat(seat));
+        match (controller, slot.input) {
+            (Some(controller), _) => {
+                player.insert(controller);
+            }
+            (None, SlotInput::Gamepad(gamepad)) => {
+                player.insert(GamepadControls(gamepad));
+            }
+            _ => {}
+        }
+    }
+}
+
+/**
+ * Hands the players of a running match the keys of their slot again, so keys rebound from the
+ * pause menu steer straight away rather than from the next match on.
+ */
+pub fn apply_key_bindings(
+    lobby: Res<Lobby>,
+    bindings: Res<KeyBindings>,
+    mut players: Query<(&LobbySeat, &mut ControlScheme), Without<AiController>>,
+) {
+    for (seat, mut control_scheme) in players.iter_mut() {
+        if let Some(SlotInput::Keys(keys)) = lobby.slots.get(seat.0).map(|slot| slot.input) {
+            *control_scheme = bindings.sets[keys].control_scheme();
+        }
+    }
+}
+
+/// The list of slots on the lobby screen, rebuilt whenever the lobby changes.
+#[derive(Component)]
+pub struct LobbyPanel;
+
+fn slot_label(lobby: &Lobby, bindings: &KeyBindings, i: usize) -> String {
+    let slot = &lobby.slots[i];
+    let (input, ready_key) = match slot.input {
+        SlotInput::Keys(keys) => {
+            let key_set = &bindings.sets[keys];
+            let label = key_set.label(&format!("KEYS {}", keys + 1));
+            (label, key_label(key_set.action))
+        }
+        SlotInput::Gamepad(gamepad) => (format!("PAD {}", gamepad.id + 1), "A".to_string()),
+    };
+    let name = if lobby.editing == Some(i) {
+        format!("{}_", slot.name)
+    } else {
+        slot.name.clone()
+    };
+    let controller = slot.bot.as_deref().unwrap_or("human").to_uppercase();
+    let status = if slot.ready {
+        "READY".to_string()
+    } else {
+        format!("{ready_key}: READY")
+    };
+    format!(
+        "P{} {:<12} {:<10} {:<7} {}",
+        i + 1,
+        name,
+        controller,
+        input,
+        status
+    )
+}
+
+pub fn update_lobby_panel(
+    mut commands: Commands,
+    lobby: Res<Lobby>,
+    bindings: Res<KeyBindings>,
+    sprites: Res<GameSprites>,
+    asset_server: Res<AssetServer>,
+    panels: Query<(Entity, Ref<LobbyPanel>)>,
+) {
+    for (panel, added) in panels.iter() {
+        if !lobby.is_changed() && !bindings.is_changed() && !added.is_added() {
+            continue;
+        }
+        let font = asset_server.load("fonts/visitor.ttf");
+        commands.entity(panel).despawn_descendants();
+        commands.entity(panel).with_children(|builder| {
+            for i in 0..MAX_SLOTS {
+                builder
+                    .spawn(NodeBundle {
+                        style: Style {
+                            height: Val::Px(40.),
+                            align_items: AlignItems::Center,
+                            column_gap: Val::Px(12.),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        let label = if i < lobby.slots.len() {
+                            let (image, atlas) = sprites.snake_icon(lobby.slots[i].skin);
+                            row.spawn((
+                                ImageBundle {
+                                    style: Style {
+                                        width: Val::Px(32.),
+                                        height: Val::Px(32.),
+                                        ..default()
+                                    },
+                                    image,
+                                    ..default()
+                                },
+                                atlas,
+                            ));
+                            slot_label(&lobby, &bindings, i)
+                        } else {
+                            format!("P{} PRESS ANY KEY OF A FREE KEY SET TO JOIN", i + 1)
+                        };
+                        row.spawn(TextBundle::from_section(
+                            label,
+                            TextStyle {
+                                font_size: 30.,
+                                color: Color::WHITE,
+                                font: font.clone(),
+                            },
+                        ));
+                    });
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::input::{
+        gamepad::{
+            GamepadButtonChangedEvent, GamepadConnection, GamepadConnectionEvent, GamepadEvent,
+            GamepadInfo,
+        },
+        InputPlugin,
+    };
+
+    use super::*;
+
+    const PAD: Gamepad = Gamepad { id: 0 };
+
+    fn lobby_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(InputPlugin)
+            .init_resource::<GamepadDirections>()
+            .insert_resource(BrainRegistry::default())
+            .init_resource::<Lobby>()
+            .add_systems(Update, lobby_gamepads);
+        app.world_mut()
+            .send_event(GamepadEvent::Connection(GamepadConnectionEvent::new(
+                PAD,
+                GamepadConnection::Connected(GamepadInfo {
+                    name: "pad".to_string(),
+                }),
+            )));
+        app.update();
+        app
+    }
+
+    fn tap(app: &mut App, button_type: GamepadButtonType) {
+        for value in [1., 0.] {
+            app.world_mut()
+                .send_event(GamepadEvent::Button(GamepadButtonChangedEvent::new(
+                    PAD,
+                    button_type,
+                    value,
+                )));
+            app.update();
+        }
+    }
+
+    fn slots(app: &App) -> &[LobbySlot] {
+        &app.world().resource::<Lobby>().slots
+    }
+
+    #[test]
+    fn a_connected_gamepad_joins_the_lobby() {
+        let app = lobby_app();
+        assert_eq!(slots(&app).len(), 1);
+        assert_eq!(slots(&app)[0].input, SlotInput::Gamepad(PAD));
+        assert!(!slots(&app)[0].ready);
+    }
+
+    #[test]
+    fn south_toggles_ready() {
+        let mut app = lobby_app();
+        tap(&mut app, GamepadButtonType::South);
+        assert!(slots(&app)[0].ready);
+        tap(&mut app, GamepadButtonType::East);
+        assert!(slots(&app)[0].ready);
+        tap(&mut app, GamepadButtonType::South);
+        assert!(!slots(&app)[0].ready);
+    }
+
+    #[test]
+    fn a_disconnected_gamepad_leaves_the_lobby() {
+        let mut app = lobby_app();
+        app.world_mut()
+            .send_event(GamepadEvent::Connection(GamepadConnectionEvent::new(
+                PAD,
+                GamepadConnection::Disconnected,
+            )));
+        app.update();
+        assert!(slots(&app).is_empty());
+    }
+}
@@ -1,12 +1,23 @@
-use bevy::{ecs::schedule::Stepping, prelude::*, utils::HashMap};
+use bevy::{ecs::schedule::Stepping, prelude::*};
 
 use crate::{
-    game::prelude::{
-        ControlScheme, Named, Player, PlayerBundle, Score, SnakeDirection, SnakeHeadRef,
-        SnakeTextureIndex,
+    controls::{controls_key_pressed, open_controls_screen, ControlsScreen},
+    game::{
+        grid::{ArenaMode, Grid},
+        level::{Level, LevelAssets, SelectedLevel},
+        replay::Playback,
+        rounds::NextRound,
+        rules::GameRules,
     },
+    netplay::NetSession,
     GameState,
 };
+use lobby::{
+    apply_key_bindings, lobby_gamepads, lobby_keys, name_entry, not_editing_name, rematch,
+    reset_lobby, spawn_lobby_players, start_when_ready, update_lobby_panel, Lobby, LobbyPanel,
+};
+
+pub mod lobby;
 
 pub struct EnterGamePlugin;
 impl Plugin for EnterGamePlugin {
@@ -14,60 +25,142 @@ impl Plugin for EnterGamePlugin {
         let mut stepping = Stepping::new();
         stepping.add_schedule(Update);
 
-        app.add_systems(OnEnter(GameState::EnterGame), init_char_selection)
-            .add_systems(Update, on_enter_keys)
+        app.init_resource::<Lobby>()
+            .add_systems(
+                OnEnter(GameState::EnterGame),
+                (reset_lobby, init_char_selection),
+            )
+            // online matches are started by the host's `NetSession`
+            .add_systems(
+                Update,
+                (lobby_keys, lobby_gamepads, name_entry, start_when_ready)
+                    .chain()
+                    .run_if(in_state(GameState::EnterGame))
+                    .run_if(in_state(ControlsScreen::Closed))
+                    .run_if(not(resource_exists::<NetSession>)),
+            )
+            .add_systems(
+                Update,
+                (
+                    (
+                        arena_mode_keys,
+                        level_keys,
+                        open_controls_screen.run_if(controls_key_pressed),
+                    )
+                        .run_if(not_editing_name)
+                        .run_if(in_state(ControlsScreen::Closed)),
+                    update_arena_mode_text,
+                    update_level_text,
+                    update_lobby_panel,
+                )
+                    .run_if(in_state(GameState::EnterGame)),
+            )
+            .add_systems(
+                OnTransition {
+                    exited: GameState::EnterGame,
+                    entered: GameState::InGame,
+                },
+                spawn_lobby_players,
+            )
+            // a restarted match is played again by the same players
+            .add_systems(
+                OnEnter(GameState::LeaveGame),
+                rematch.run_if(not(resource_exists::<Playback>)),
+            )
+            .add_systems(
+                OnTransition {
+                    exited: GameState::LeaveGame,
+                    entered: GameState::InGame,
+                },
+                // the next round of a versus match keeps its players
+                spawn_lobby_players.run_if(not(
+                    resource_exists::<Playback>.or_else(resource_exists::<NextRound>)
+                )),
+            )
+            .add_systems(
+                OnExit(ControlsScreen::Open),
+                apply_key_bindings.run_if(
+                    in_state(GameState::InGame).and_then(not(resource_exists::<NetSession>)),
+                ),
+            )
             .insert_resource(stepping);
     }
 }
 
-pub fn on_enter_keys(
-    mut commands: Commands,
+#[derive(Component)]
+pub struct ArenaModeText;
+
+fn arena_mode_label(mode: ArenaMode) -> &'static str {
+    match mode {
+        ArenaMode::Walled => "TAB: WALLED ARENA",
+        ArenaMode::Wrap => "TAB: WRAP AROUND ARENA",
+    }
+}
+
+pub fn arena_mode_keys(keyboard: Res<ButtonInput<KeyCode>>, mut grid: ResMut<Grid>) {
+    if keyboard.just_pressed(KeyCode::Tab) {
+        grid.mode = match grid.mode {
+            ArenaMode::Walled => ArenaMode::Wrap,
+            ArenaMode::Wrap => ArenaMode::Walled,
+        };
+    }
+}
+
+pub fn update_arena_mode_text(grid: Res<Grid>, mut texts: Query<&mut Text, With<ArenaModeText>>) {
+    for mut text in texts.iter_mut() {
+        text.sections[0].value = arena_mode_label(grid.mode).to_string();
+    }
+}
+
+#[derive(Component)]
+pub struct LevelText;
+
+fn level_label(
+    selected: &SelectedLevel,
+    level_assets: &LevelAssets,
+    levels: &Assets<Level>,
+    rules: &GameRules,
+) -> String {
+    let level = selected.level(Some(level_assets), levels, rules);
+    format!("L: ARENA {}", level.name.to_uppercase())
+}
+
+pub fn level_keys(
     keyboard: Res<ButtonInput<KeyCode>>,
-    mut next_state: ResMut<NextState<GameState>>,
+    level_assets: Res<LevelAssets>,
+    mut selected: ResMut<SelectedLevel>,
 ) {
-    if keyboard.just_pressed(KeyCode::Space) {
-        next_state.set(GameState::InGame);
+    if keyboard.just_pressed(KeyCode::KeyL) {
+        selected.next(&level_assets);
     }
 }
-pub fn init_char_selection(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let key_map_1: HashMap<KeyCode, SnakeDirection> = {
-        let mut key_map = HashMap::new();
-        key_map.insert(KeyCode::ArrowUp, SnakeDirection::Up);
-        key_map.insert(KeyCode::ArrowLeft, SnakeDirection::Left);
-        key_map.insert(KeyCode::ArrowDown, SnakeDirection::Down);
-        key_map.insert(KeyCode::ArrowRight, SnakeDirection::Right);
-        key_map
-    };
-    let key_map_2: HashMap<KeyCode, SnakeDirection> = {
-        let mut key_map = HashMap::new();
-        key_map.insert(KeyCode::KeyW, SnakeDirection::Up);
-        key_map.insert(KeyCode::KeyA, SnakeDirection::Left);
-        key_map.insert(KeyCode::KeyS, SnakeDirection::Down);
-        key_map.insert(KeyCode::KeyD, SnakeDirection::Right);
-        key_map
-    };
 
-    commands.spawn(PlayerBundle {
-        player: Player {},
-        name: Named("Player 1".to_string()),
-        control_scheme: ControlScheme {
-            directional_controls: key_map_1,
-        },
-        snake_head_ref: SnakeHeadRef(None),
-        score: Score { value: 0 },
-        texture_index: SnakeTextureIndex(0),
-    });
-    commands.spawn(PlayerBundle {
-        player: Player {},
-        name: Named("Player 2".to_string()),
-        control_scheme: ControlScheme {
-            directional_controls: key_map_2,
-        },
-        snake_head_ref: SnakeHeadRef(None),
-        score: Score { value: 0 },
-        texture_index: SnakeTextureIndex(1),
-    });
+pub fn update_level_text(
+    selected: Res<SelectedLevel>,
+    level_assets: Res<LevelAssets>,
+    levels: Res<Assets<Level>>,
+    rules: Res<GameRules>,
+    mut texts: Query<&mut Text, With<LevelText>>,
+) {
+    if !selected.is_changed() {
+        return;
+    }
+    for mut text in texts.iter_mut() {
+        text.sections[0].value = level_label(&selected, &level_assets, &levels, &rules);
+    }
+}
 
+#[allow(clippy::too_many_arguments)]
+pub fn init_char_selection(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    grid: Res<Grid>,
+    selected_level: Res<SelectedLevel>,
+    level_assets: Res<LevelAssets>,
+    levels: Res<Assets<Level>>,
+    rules: Res<GameRules>,
+    net_session: Option<Res<NetSession>>,
+) {
     commands
         .spawn((
             NodeBundle {
@@ -89,6 +182,7 @@ pub fn init_char_selection(mut commands: Commands, asset_server: Res<AssetServer
                         width: Val::Percent(100.),
                         height: Val::Percent(40.),
                         display: Display::Flex,
+                        flex_direction: FlexDirection::Column,
                         justify_content: JustifyContent::Center,
                         align_items: AlignItems::Center,
                         ..default()
@@ -106,7 +200,7 @@ pub fn init_char_selection(mut commands: Commands, asset_server: Res<AssetServer
                 .with_children(|builder| {
                     builder.spawn((TextBundle {
                         text: Text::from_section(
-                            "SPACE TO START",
+                            "PRESS A PLAYER KEY TO JOIN",
                             TextStyle {
                                 font_size: 50.,
                                 color: Color::WHITE,
@@ -116,6 +210,65 @@ pub fn init_char_selection(mut commands: Commands, asset_server: Res<AssetServer
                         ),
                         ..default()
                     },));
+                    builder.spawn((
+                        TextBundle {
+                            text: Text::from_section(
+                                arena_mode_label(grid.mode),
+                                TextStyle {
+                                    font_size: 30.,
+                                    color: Color::WHITE,
+                                    font: asset_server.load("fonts/visitor.ttf"),
+                                },
+                            ),
+                            ..default()
+                        },
+                        ArenaModeText,
+                    ));
+                    builder.spawn((
+                        TextBundle {
+                            text: Text::from_section(
+                                level_label(&selected_level, &level_assets, &levels, &rules),
+                                TextStyle {
+                                    font_size: 30.,
+                                    color: Color::WHITE,
+                                    font: asset_server.load("fonts/visitor.ttf"),
+                                },
+                            ),
+                            ..default()
+                        },
+                        LevelText,
+                    ));
+                });
+            // online matches are always the host against the peer that joined
+            if net_session.is_none() {
+                builder.spawn((
+                    NodeBundle {
+                        style: Style {
+                            display: Display::Flex,
+                            flex_direction: FlexDirection::Column,
+                            align_self: AlignSelf::Center,
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    LobbyPanel,
+                ));
+                builder.spawn(TextBundle {
+                    style: Style {
+                        align_self: AlignSelf::Center,
+                        margin: UiRect::top(Val::Px(24.)),
+                        ..default()
+                    },
+                    text: Text::from_section(
+                        "LEFT/RIGHT: SKIN  UP/DOWN: HUMAN OR AI  F1-F4: NAME  F9: CONTROLS",
+                        TextStyle {
+                            font_size: 20.,
+                            color: Color::WHITE,
+                            font: asset_server.load("fonts/visitor.ttf"),
+                        },
+                    ),
+                    ..default()
                 });
+            }
         });
 }
@@ -0,0 +1,133 @@
+use bevy::prelude::*;
+
+use super::brain::{BoardQuery, BoardSnapshot, BrainRegistry, SnakeBrain};
+use super::components::SnakeHead;
+use super::prelude::{DirectionQueue, SnakeDirection, SnakeHeadRef};
+
+/// The bots that come with the game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AiStrategy {
+    /// Heads straight for the closest apple, only avoiding the very next cell if it is taken.
+    Greedy,
+    /// Follows the shortest free path to an apple, as long as it does not lead into a dead end.
+    Pathfinder,
+    /// Moves to wherever leaves the most room to move in, then towards the apple.
+    FloodFill,
+}
+
+impl SnakeBrain for AiStrategy {
+    fn decide(&mut self, board: &BoardSnapshot, me: usize) -> SnakeDirection {
+        let snake = &board.snakes[me];
+        let length = snake.cells.len();
+        let moves = board.safe_moves(snake);
+        let choice = match self {
+            AiStrategy::Greedy => moves
+                .into_iter()
+                .min_by_key(|(_, cell)| board.closest_apple_distance(*cell))
+                .map(|(direction, _)| direction),
+            AiStrategy::Pathfinder => board
+                .path_to_apple(snake)
+                .filter(|direction| {
+                    let next = board.next(snake.head(), direction);
+                    board.room(next, length + 1) > length
+                })
+                .or_else(|| Some(AiStrategy::FloodFill.decide(board, me))),
+            AiStrategy::FloodFill => {
+                let limit = (length * 2).max(16);
+                moves
+                    .into_iter()
+                    .max_by_key(|(_, cell)| {
+                        (
+                            board.room(*cell, limit),
+                            -board.closest_apple_distance(*cell),
+                        )
+                    })
+                    .map(|(direction, _)| direction)
+            }
+        };
+        // every move is fatal, so it might as well carry on
+        choice.unwrap_or_else(|| snake.direction.clone())
+    }
+}
+
+/**
+ * Steers a `Player`'s snake instead of a `ControlScheme`. The chosen turns go through the
+ * player's `DirectionQueue` like key presses do.
+ */
+#[derive(Component)]
+pub struct AiController {
+    /// The name the brain is registered under.
+    pub name: String,
+    pub brain: Box<dyn SnakeBrain>,
+}
+
+impl AiController {
+    pub fn from_registry(registry: &BrainRegistry, name: &str) -> Option<Self> {
+        Some(AiController {
+            name: name.to_string(),
+            brain: registry.create(name)?,
+        })
+    }
+}
+
+/// The bot of each player slot by name, `None` for a human. Slots past the end are human.
+#[derive(Resource, Default, Clone)]
+pub struct AiSlots(pub Vec<Option<String>>);
+
+impl AiSlots {
+    pub fn get(&self, slot: usize) -> Option<&str> {
+        self.0.get(slot).and_then(Option::as_deref)
+    }
+
+    /// Parses a comma separated list such as `human,greedy,floodfill`.
+    pub fn parse(list: &str, registry: &BrainRegistry) -> Result<Self, &'static str> {
+        list.split(',')
+            .map(|name| match name.trim() {
+                "human" => Ok(None),
+                name if registry.contains(name) => Ok(Some(name.to_string())),
+                _ => Err("unknown AI, see --list-bots"),
+            })
+            .collect::<Result<_, _>>()
+            .map(AiSlots)
+    }
+
+    /// The controller for the given slot, if it is not played by a human.
+    pub fn controller(&self, slot: usize, registry: &BrainRegistry) -> Option<AiController> {
+        AiController::from_registry(registry, self.get(slot)?)
+    }
+}
+
+/**
+ * Asks every AI snake for its next turn right before the snakes move. All of them are handed the
+ * board first, so slow bots keep each other waiting no longer than the slowest one.
+ */
+pub fn ai_steer(
+    board: BoardQuery,
+    mut players: Query<(&mut AiController, &mut DirectionQueue, &SnakeHeadRef)>,
+    heads: Query<&SnakeHead>,
+) {
+    let (board, snake_players) = board.snapshot();
+
+    for (me, player) in snake_players.iter().enumerate() {
+        if !board.snakes[me].alive {
+            continue;
+        }
+        if let Ok((mut controller, ..)) = players.get_mut(*player) {
+            controller.brain.prepare(&board, me);
+        }
+    }
+    for (me, player) in snake_players.iter().enumerate() {
+        if !board.snakes[me].alive {
+            continue;
+        }
+        let Ok((mut controller, mut queue, head_ref)) = players.get_mut(*player) else {
+            continue;
+        };
+        let Some(Ok(head)) = head_ref.0.map(|head| heads.get(head)) else {
+            continue;
+        };
+        let direction = controller.brain.decide(&board, me);
+        queue.0.clear();
+        queue.push(direction, &head.direction);
+    }
+}
@@ -0,0 +1,317 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use super::ai::AiStrategy;
+use super::components::{Apple, Dead, ElapsedTicks, SnakeHead};
+use super::external::{ExternalBrain, DEFAULT_BOT_BUDGET};
+use super::grid::{Grid, GridPos};
+use super::level::{ActiveLevel, LevelTile};
+use super::prelude::{BodyRef, Named, Player, Score, SnakeDirection, SnakeHeadRef};
+
+pub const DIRECTIONS: [SnakeDirection; 4] = [
+    SnakeDirection::Up,
+    SnakeDirection::Down,
+    SnakeDirection::Left,
+    SnakeDirection::Right,
+];
+
+/// One snake as it is seen by a `SnakeBrain`.
+#[derive(Debug, Clone)]
+pub struct SnakeView {
+    pub name: String,
+    /// Head first.
+    pub cells: Vec<IVec2>,
+    pub direction: SnakeDirection,
+    pub alive: bool,
+    pub score: u32,
+}
+
+impl SnakeView {
+    pub fn head(&self) -> IVec2 {
+        self.cells[0]
+    }
+}
+
+/**
+ * A read-only copy of the board at the start of a tick, before anything has moved.
+ * Cell (0, 0) is the bottom left corner, like `GridPos`.
+ */
+#[derive(Debug, Clone)]
+pub struct BoardSnapshot {
+    /// Ticks played so far in this match.
+    pub tick: u32,
+    pub grid: Grid,
+    pub walls: Vec<IVec2>,
+    /// Where a head entering a portal comes out.
+    pub portals: HashMap<IVec2, IVec2>,
+    pub snakes: Vec<SnakeView>,
+    pub apples: Vec<IVec2>,
+    blocked: HashSet<IVec2>,
+}
+
+impl BoardSnapshot {
+    pub fn new(
+        tick: u32,
+        grid: Grid,
+        walls: Vec<IVec2>,
+        portals: HashMap<IVec2, IVec2>,
+        snakes: Vec<SnakeView>,
+        apples: Vec<IVec2>,
+    ) -> Self {
+        let blocked = walls
+            .iter()
+            .chain(snakes.iter().flat_map(|snake| snake.cells.iter()))
+            .copied()
+            .collect();
+        BoardSnapshot {
+            tick,
+            grid,
+            walls,
+            portals,
+            snakes,
+            apples,
+            blocked,
+        }
+    }
+
+    /// Whether a head moving into `pos` crashes. Dead snakes are obstacles too.
+    pub fn is_blocked(&self, pos: IVec2) -> bool {
+        !self.grid.contains(pos) || self.blocked.contains(&pos)
+    }
+
+    /// The cell a head at `pos` ends up in after a step, portals included.
+    pub fn next(&self, pos: IVec2, direction: &SnakeDirection) -> IVec2 {
+        let next = self.grid.step(pos, direction);
+        self.portals.get(&next).copied().unwrap_or(next)
+    }
+
+    /// Steps between two cells, ignoring anything in the way.
+    pub fn distance(&self, from: IVec2, to: IVec2) -> i32 {
+        self.grid.offset(from, to).abs().element_sum()
+    }
+
+    pub fn closest_apple_distance(&self, pos: IVec2) -> i32 {
+        self.apples
+            .iter()
+            .map(|apple| self.distance(pos, *apple))
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// The turns the snake can take without reversing or crashing straight away, with the cell
+    /// each one leads to.
+    pub fn safe_moves(&self, snake: &SnakeView) -> Vec<(SnakeDirection, IVec2)> {
+        DIRECTIONS
+            .iter()
+            .filter(|direction| **direction != snake.direction.opposite())
+            .map(|direction| (direction.clone(), self.next(snake.head(), direction)))
+            .filter(|(_, cell)| !self.is_blocked(*cell))
+            .collect()
+    }
+
+    /// How many free cells can be reached from `from`, counting no further than `limit`.
+    pub fn room(&self, from: IVec2, limit: usize) -> usize {
+        let mut seen = HashSet::from([from]);
+        let mut open = VecDeque::from([from]);
+        while let Some(pos) = open.pop_front() {
+            if seen.len() >= limit {
+                break;
+            }
+            for direction in DIRECTIONS.iter() {
+                let next = self.next(pos, direction);
+                if !self.is_blocked(next) && seen.insert(next) {
+                    open.push_back(next);
+                }
+            }
+        }
+        seen.len()
+    }
+
+    /// The first step of a shortest free path from the snake's head to any apple.
+    pub fn path_to_apple(&self, snake: &SnakeView) -> Option<SnakeDirection> {
+        let mut seen = HashSet::from([snake.head()]);
+        let mut open = VecDeque::new();
+        for (direction, cell) in self.safe_moves(snake) {
+            if seen.insert(cell) {
+                open.push_back((cell, direction));
+            }
+        }
+        while let Some((pos, first)) = open.pop_front() {
+            if self.apples.contains(&pos) {
+                return Some(first);
+            }
+            for direction in DIRECTIONS.iter() {
+                let next = self.next(pos, direction);
+                if !self.is_blocked(next) && seen.insert(next) {
+                    open.push_back((next, first.clone()));
+                }
+            }
+        }
+        None
+    }
+}
+
+/**
+ * Decides where a snake goes. Implement this to write a bot, and add it to
+ * `BrainRegistry::default` to make it selectable by name with `--ai` and `--tournament`.
+ */
+pub trait SnakeBrain: Send + Sync {
+    /**
+     * Called once per tick while the snake is alive. `me` is the snake's index in
+     * `board.snakes`. Turning back or keeping the current direction both carry on straight.
+     */
+    fn decide(&mut self, board: &BoardSnapshot, me: usize) -> SnakeDirection;
+
+    /**
+     * Called for every snake before any of them is asked to `decide` the tick, so brains that
+     * wait on something outside the game can all be thinking at the same time.
+     */
+    fn prepare(&mut self, _board: &BoardSnapshot, _me: usize) {}
+}
+
+pub type BrainFactory = fn() -> Box<dyn SnakeBrain>;
+
+/**
+ * The bots that can be picked by name. Names starting with `exec:` or `tcp:` are always
+ * accepted and start an `ExternalBrain`.
+ */
+#[derive(Resource, Clone)]
+pub struct BrainRegistry {
+    brains: Vec<(&'static str, BrainFactory)>,
+    /// How long external bots get to answer each tick.
+    pub bot_budget: Duration,
+}
+
+impl Default for BrainRegistry {
+    fn default() -> Self {
+        let mut registry = BrainRegistry {
+            brains: Vec::new(),
+            bot_budget: DEFAULT_BOT_BUDGET,
+        };
+        registry.register("greedy", || Box::new(AiStrategy::Greedy));
+        registry.register("pathfinder", || Box::new(AiStrategy::Pathfinder));
+        registry.register("floodfill", || Box::new(AiStrategy::FloodFill));
+        registry
+    }
+}
+
+impl BrainRegistry {
+    /// Adds a bot, replacing any bot registered under the same name.
+    pub fn register(&mut self, name: &'static str, factory: BrainFactory) {
+        self.brains.retain(|(registered, _)| *registered != name);
+        self.brains.push((name, factory));
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        ExternalBrain::is_spec(name)
+            || self
+                .brains
+                .iter()
+                .any(|(registered, _)| *registered == name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.brains.iter().map(|(name, _)| *name)
+    }
+
+    /// A fresh instance of the named bot, or `None` if there is none or it could not be started.
+    pub fn create(&self, name: &str) -> Option<Box<dyn SnakeBrain>> {
+        if ExternalBrain::is_spec(name) {
+            return match ExternalBrain::open(name, self.bot_budget) {
+                Ok(brain) => Some(Box::new(brain)),
+                Err(err) => {
+                    warn!("could not start bot {name}: {err}");
+                    None
+                }
+            };
+        }
+        self.brains
+            .iter()
+            .find(|(registered, _)| *registered == name)
+            .map(|(_, factory)| factory())
+    }
+}
+
+/**
+ * Everything needed to take a `BoardSnapshot` from inside a system.
+ */
+#[derive(SystemParam)]
+pub struct BoardQuery<'w, 's> {
+    ticks: Res<'w, ElapsedTicks>,
+    grid: Res<'w, Grid>,
+    level: Res<'w, ActiveLevel>,
+    players: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Named,
+            &'static Score,
+            &'static SnakeHeadRef,
+        ),
+        With<Player>,
+    >,
+    heads: Query<
+        'w,
+        's,
+        (
+            &'static GridPos,
+            &'static SnakeHead,
+            &'static BodyRef,
+            Has<Dead>,
+        ),
+    >,
+    body_parts: Query<'w, 's, &'static GridPos>,
+    apples: Query<'w, 's, &'static GridPos, With<Apple>>,
+}
+
+impl BoardQuery<'_, '_> {
+    /// The board, and the `Player` entity of each snake in it.
+    pub fn snapshot(&self) -> (BoardSnapshot, Vec<Entity>) {
+        let mut players = Vec::new();
+        let mut snakes = Vec::new();
+        for (player, name, score, head_ref) in self.players.iter() {
+            let Some(Ok((head_pos, head, body_ref, dead))) =
+                head_ref.0.map(|head| self.heads.get(head))
+            else {
+                continue;
+            };
+            let body = body_ref
+                .0
+                .iter()
+                .filter_map(|part| self.body_parts.get(*part).ok());
+            players.push(player);
+            snakes.push(SnakeView {
+                name: name.0.clone(),
+                cells: std::iter::once(head_pos)
+                    .chain(body)
+                    .map(|pos| pos.0)
+                    .collect(),
+                direction: head.direction.clone(),
+                alive: !dead,
+                score: score.value,
+            });
+        }
+
+        let walls = self
+            .level
+            .cells()
+            .filter(|(_, tile)| *tile == LevelTile::Wall)
+            .map(|(pos, _)| pos)
+            .collect();
+        let board = BoardSnapshot::new(
+            self.ticks.0,
+            *self.grid,
+            walls,
+            self.level.portals.clone(),
+            snakes,
+            self.apples.iter().map(|pos| pos.0).collect(),
+        );
+        (board, players)
+    }
+}
@@ -1,18 +1,21 @@
 use bevy::{
     math::{vec2, vec3},
     prelude::*,
+    utils::HashSet,
 };
 
-use super::components::Bounding;
+use serde::{Deserialize, Serialize};
+
+use super::components::{Dead, GrowSnakeEvent, SnakeBodyPart, SnakeHead, Tail};
+use super::grid::GridPos;
+use super::occupancy::{Occupancy, Occupant};
+use super::prelude::BodyRef;
+use super::rules::GameRules;
 
 pub fn distance_between(a: &Vec3, b: &Vec3) -> f32 {
     a.distance(*b)
 }
 
-pub fn circles_touching(a: &Transform, ar: &Bounding, b: &Transform, br: &Bounding) -> bool {
-    distance_between(&a.translation, &b.translation) < (ar.0 + br.0)
-}
-
 pub fn distance_to_move(a: &Vec3, ar: f32, b: &Vec3, br: f32) -> f32 {
     ar + br - distance_between(a, b)
 }
@@ -25,3 +28,573 @@ pub fn rotate_point(fp: Vec2, pt: Vec2, a: f32) -> Vec2 {
 
     vec2(fp.x + x_rot, fp.y + y_rot)
 }
+
+/**
+ * What a snake head ran into this tick.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionKind {
+    SelfBite,
+    BodyBite { victim: Entity },
+    HeadOn { other: Entity },
+    Wall,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CollisionRule {
+    /// The snake that ran into something dies. In a head-on collision both snakes are biters.
+    #[default]
+    BiterDies,
+    BothDie,
+    LongerWins,
+    /// The bitten snake is cut at the contact point and the biter grows by the cut length.
+    StealSegments,
+    /// The bitten snake is cut at the contact point and the cut segments are lost.
+    CutAtContact,
+}
+
+/**
+ * The rule applied to each kind of collision. Running into a wall is always fatal.
+ * Steal and cut fall back to both snakes dying for head-on collisions, and a self-bite
+ * can only ever cut the snake's own tail off.
+ */
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CollisionRules {
+    pub self_bite: CollisionRule,
+    pub body_bite: CollisionRule,
+    pub head_on: CollisionRule,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionOutcome {
+    BiterDied,
+    /// The other snake died, including a bite at the neck which leaves nothing to cut.
+    VictimDied,
+    BothDied,
+    Cut {
+        segments: usize,
+    },
+    Stolen {
+        segments: usize,
+    },
+}
+
+#[derive(Debug, Event)]
+pub struct CollisionEvent {
+    pub biter: Entity,
+    pub kind: CollisionKind,
+    pub cell: IVec2,
+    pub outcome: CollisionOutcome,
+}
+
+type SnakeQuery<'w, 's> =
+    Query<'w, 's, (Entity, &'static GridPos, &'static mut BodyRef, Has<Dead>), With<SnakeHead>>;
+
+/**
+ * Claims the cell of every head that moved into free space and classifies the rest.
+ */
+fn classify(
+    occupancy: &mut Occupancy,
+    heads: &SnakeQuery,
+    body_parts: &Query<&GridPos, With<SnakeBodyPart>>,
+) -> Vec<(Entity, IVec2, CollisionKind)> {
+    let neck = |snake: Entity| {
+        heads
+            .get(snake)
+            .ok()
+            .and_then(|(_, _, body_ref, _)| body_ref.0.first().copied())
+            .and_then(|part| body_parts.get(part).ok())
+            .map(|pos| pos.0)
+    };
+
+    let mut contacts = Vec::new();
+    for (entity, head_pos, _, dead) in heads.iter() {
+        if dead {
+            continue;
+        }
+        let cell = head_pos.0;
+        let kind = match occupancy.get(cell) {
+            None | Some(Occupant::Apple(_)) => {
+                occupancy.set(cell, Occupant::Head(entity));
+                continue;
+            }
+            Some(Occupant::Wall) => CollisionKind::Wall,
+            Some(Occupant::Head(other)) => match heads.get(other) {
+                Ok((_, _, _, false)) => CollisionKind::HeadOn { other },
+                _ => CollisionKind::BodyBite { victim: other },
+            },
+            Some(Occupant::Body(owner)) if owner == entity => CollisionKind::SelfBite,
+            Some(Occupant::Body(owner)) => {
+                // two heads swapping cells pass through each other's necks
+                let swapped = heads
+                    .get(owner)
+                    .is_ok_and(|(_, pos, _, dead)| !dead && Some(pos.0) == neck(entity))
+                    && neck(owner) == Some(cell);
+                if swapped {
+                    CollisionKind::HeadOn { other: owner }
+                } else {
+                    CollisionKind::BodyBite { victim: owner }
+                }
+            }
+        };
+        contacts.push((entity, cell, kind));
+    }
+    contacts
+}
+
+/**
+ * Removes every segment of `snake` from the one at `cell` to the tail.
+ * Returns how many segments were removed, or `None` if no segment is on that cell.
+ */
+fn cut_snake(
+    commands: &mut Commands,
+    occupancy: &mut Occupancy,
+    heads: &mut SnakeQuery,
+    body_parts: &Query<&GridPos, With<SnakeBodyPart>>,
+    snake: Entity,
+    cell: IVec2,
+) -> Option<usize> {
+    let (_, _, mut body_ref, _) = heads.get_mut(snake).ok()?;
+    let index = body_ref
+        .0
+        .iter()
+        .position(|part| body_parts.get(*part).is_ok_and(|pos| pos.0 == cell))?;
+
+    let removed = body_ref.0.split_off(index);
+    for part in removed.iter() {
+        if let Ok(pos) = body_parts.get(*part) {
+            occupancy.clear_if(pos.0, Occupant::Body(snake));
+        }
+        commands.entity(*part).despawn_recursive();
+    }
+    if let Some(tail) = body_ref.0.last() {
+        commands.entity(*tail).insert(Tail);
+    }
+    Some(removed.len())
+}
+
+fn snake_len(heads: &SnakeQuery, snake: Entity) -> usize {
+    heads
+        .get(snake)
+        .map_or(0, |(_, _, body_ref, _)| body_ref.0.len())
+}
+
+/**
+ * Runs after movement: classifies what every head ran into, applies the configured
+ * `CollisionRules` of the game rules and reports each outcome as a `CollisionEvent`.
+ */
+#[allow(clippy::too_many_arguments)]
+pub fn resolve_collisions(
+    mut commands: Commands,
+    rules: Res<GameRules>,
+    mut occupancy: ResMut<Occupancy>,
+    mut heads: SnakeQuery,
+    body_parts: Query<&GridPos, With<SnakeBodyPart>>,
+    mut grow_snake: EventWriter<GrowSnakeEvent>,
+    mut collisions: EventWriter<CollisionEvent>,
+) {
+    let contacts = classify(&mut occupancy, &heads, &body_parts);
+    let mut dead: HashSet<Entity> = heads
+        .iter()
+        .filter(|(_, _, _, dead)| *dead)
+        .map(|(entity, _, _, _)| entity)
+        .collect();
+
+    for (biter, cell, kind) in contacts {
+        // already lost to an earlier collision this tick, e.g. the other half of a swap
+        if dead.contains(&biter) {
+            continue;
+        }
+
+        let rule = match kind {
+            CollisionKind::Wall => CollisionRule::BiterDies,
+            CollisionKind::SelfBite => rules.collisions.self_bite,
+            // dead snakes are just obstacles
+            CollisionKind::BodyBite { victim } | CollisionKind::HeadOn { other: victim }
+                if dead.contains(&victim) =>
+            {
+                CollisionRule::BiterDies
+            }
+            CollisionKind::BodyBite { .. } => rules.collisions.body_bite,
+            CollisionKind::HeadOn { .. } => match rules.collisions.head_on {
+                CollisionRule::BiterDies
+                | CollisionRule::StealSegments
+                | CollisionRule::CutAtContact => CollisionRule::BothDie,
+                rule => rule,
+            },
+        };
+        let victim = match kind {
+            CollisionKind::BodyBite { victim } | CollisionKind::HeadOn { other: victim } => victim,
+            CollisionKind::SelfBite | CollisionKind::Wall => biter,
+        };
+
+        let outcome = match rule {
+            CollisionRule::BiterDies => CollisionOutcome::BiterDied,
+            CollisionRule::BothDie if victim == biter => CollisionOutcome::BiterDied,
+            CollisionRule::BothDie => CollisionOutcome::BothDied,
+            CollisionRule::LongerWins if victim == biter => CollisionOutcome::BiterDied,
+            CollisionRule::LongerWins => {
+                let biter_len = snake_len(&heads, biter);
+                let victim_len = snake_len(&heads, victim);
+                match biter_len.cmp(&victim_len) {
+                    std::cmp::Ordering::Greater => CollisionOutcome::VictimDied,
+                    std::cmp::Ordering::Less => CollisionOutcome::BiterDied,
+                    std::cmp::Ordering::Equal => CollisionOutcome::BothDied,
+                }
+            }
+            CollisionRule::StealSegments | CollisionRule::CutAtContact => {
+                let cut = cut_snake(
+                    &mut commands,
+                    &mut occupancy,
+                    &mut heads,
+                    &body_parts,
+                    victim,
+                    cell,
+                );
+                match cut {
+                    Some(segments) if rule == CollisionRule::StealSegments && victim != biter => {
+                        grow_snake.send(GrowSnakeEvent {
+                            snake: biter,
+                            segments,
+                        });
+                        if snake_len(&heads, victim) == 0 {
+                            CollisionOutcome::VictimDied
+                        } else {
+                            CollisionOutcome::Stolen { segments }
+                        }
+                    }
+                    Some(_) if snake_len(&heads, victim) == 0 => CollisionOutcome::VictimDied,
+                    Some(segments) => CollisionOutcome::Cut { segments },
+                    None => CollisionOutcome::BiterDied,
+                }
+            }
+        };
+
+        let (biter_dies, victim_dies) = match outcome {
+            CollisionOutcome::BiterDied => (true, false),
+            CollisionOutcome::VictimDied => (false, true),
+            CollisionOutcome::BothDied => (true, true),
+            CollisionOutcome::Cut { .. } | CollisionOutcome::Stolen { .. } => (false, false),
+        };
+        if biter_dies {
+            commands.entity(biter).insert(Dead);
+            dead.insert(biter);
+        } else {
+            // the survivor takes over the contact cell
+            occupancy.set(cell, Occupant::Head(biter));
+        }
+        if victim_dies {
+            commands.entity(victim).insert(Dead);
+            dead.insert(victim);
+        }
+
+        collisions.send(CollisionEvent {
+            biter,
+            kind,
+            cell,
+            outcome,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::grid::Grid;
+    use crate::game::prelude::{SnakeDirection, SnakeTextureIndex};
+    use crate::game::systems::grow_snake;
+    use bevy::ecs::system::RunSystemOnce;
+
+    fn world_with(collisions: CollisionRules) -> World {
+        let mut world = World::new();
+        world.insert_resource(GameRules {
+            collisions,
+            ..default()
+        });
+        world.insert_resource(Occupancy::new(&Grid::default()));
+        world.init_resource::<Events<GrowSnakeEvent>>();
+        world.init_resource::<Events<CollisionEvent>>();
+        world
+    }
+
+    /// Spawns a snake with its head on the first cell and its body along the rest.
+    fn spawn_snake(world: &mut World, cells: &[IVec2]) -> Entity {
+        let head = world
+            .spawn((
+                SnakeHead {
+                    direction: SnakeDirection::Right,
+                },
+                GridPos(cells[0]),
+                SnakeTextureIndex(0),
+            ))
+            .id();
+        let body: Vec<Entity> = cells[1..]
+            .iter()
+            .map(|cell| {
+                world
+                    .spawn((SnakeBodyPart, GridPos(*cell), SnakeTextureIndex(0)))
+                    .id()
+            })
+            .collect();
+        if let Some(tail) = body.last() {
+            world.entity_mut(*tail).insert(Tail);
+        }
+        world.entity_mut(head).insert(BodyRef(body));
+
+        let mut occupancy = world.resource_mut::<Occupancy>();
+        occupancy.set(cells[0], Occupant::Head(head));
+        for cell in cells[1..].iter() {
+            occupancy.set(*cell, Occupant::Body(head));
+        }
+        head
+    }
+
+    /// A snake heading right along row `y`, with its head at `x`.
+    fn row(x: i32, y: i32, len: i32) -> Vec<IVec2> {
+        (0..len).map(|i| IVec2::new(x - i, y)).collect()
+    }
+
+    /// Moves a head onto `cell` the way `move_snakes` does, leaving the cell to be claimed.
+    fn move_head(world: &mut World, head: Entity, cell: IVec2) {
+        let old = std::mem::replace(&mut world.get_mut::<GridPos>(head).unwrap().0, cell);
+        world
+            .resource_mut::<Occupancy>()
+            .clear_if(old, Occupant::Head(head));
+    }
+
+    fn tick(world: &mut World) {
+        let mut schedule = Schedule::default();
+        schedule.add_systems((resolve_collisions, grow_snake).chain());
+        schedule.run(world);
+    }
+
+    fn body_len(world: &World, head: Entity) -> usize {
+        world.get::<BodyRef>(head).unwrap().0.len()
+    }
+
+    fn is_dead(world: &World, head: Entity) -> bool {
+        world.get::<Dead>(head).is_some()
+    }
+
+    fn contacts(world: &mut World) -> Vec<(Entity, IVec2, CollisionKind)> {
+        world.run_system_once(
+            |mut occupancy: ResMut<Occupancy>,
+             heads: SnakeQuery,
+             body_parts: Query<&GridPos, With<SnakeBodyPart>>| {
+                classify(&mut occupancy, &heads, &body_parts)
+            },
+        )
+    }
+
+    fn outcomes(world: &World) -> Vec<CollisionOutcome> {
+        let events = world.resource::<Events<CollisionEvent>>();
+        events
+            .get_reader()
+            .read(events)
+            .map(|ev| ev.outcome)
+            .collect()
+    }
+
+    /// A snake of five biting the third segment of another, with the given length.
+    fn body_bite(collisions: CollisionRules, biter_len: i32) -> (World, Entity, Entity) {
+        let mut world = world_with(collisions);
+        let victim = spawn_snake(&mut world, &row(10, 5, 5));
+        let biter = spawn_snake(&mut world, &row(3, 3, biter_len));
+        move_head(&mut world, victim, IVec2::new(11, 5));
+        move_head(&mut world, biter, IVec2::new(8, 5));
+        tick(&mut world);
+        (world, victim, biter)
+    }
+
+    #[test]
+    fn a_free_cell_is_claimed_by_the_head() {
+        let mut world = world_with(default());
+        let snake = spawn_snake(&mut world, &row(5, 5, 3));
+        move_head(&mut world, snake, IVec2::new(6, 5));
+
+        assert!(contacts(&mut world).is_empty());
+        assert_eq!(
+            world.resource::<Occupancy>().get(IVec2::new(6, 5)),
+            Some(Occupant::Head(snake))
+        );
+    }
+
+    #[test]
+    fn leaving_the_grid_is_running_into_a_wall() {
+        let mut world = world_with(default());
+        let snake = spawn_snake(&mut world, &row(0, 5, 1));
+        move_head(&mut world, snake, IVec2::new(-1, 5));
+
+        assert_eq!(
+            contacts(&mut world),
+            vec![(snake, IVec2::new(-1, 5), CollisionKind::Wall)]
+        );
+    }
+
+    #[test]
+    fn bites_are_told_apart_by_whose_body_it_is() {
+        let mut world = world_with(default());
+        let snake = spawn_snake(&mut world, &row(10, 5, 5));
+        let other = spawn_snake(&mut world, &row(3, 3, 3));
+        move_head(&mut world, snake, IVec2::new(7, 5));
+        move_head(&mut world, other, IVec2::new(9, 5));
+
+        let mut found = contacts(&mut world);
+        found.sort_by_key(|(biter, ..)| *biter == other);
+        assert_eq!(
+            found,
+            vec![
+                (snake, IVec2::new(7, 5), CollisionKind::SelfBite),
+                (
+                    other,
+                    IVec2::new(9, 5),
+                    CollisionKind::BodyBite { victim: snake }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn heads_meeting_on_a_cell_are_head_on() {
+        let mut world = world_with(default());
+        let left = spawn_snake(&mut world, &row(4, 5, 3));
+        let right = spawn_snake(&mut world, &[6, 7, 8].map(|x| IVec2::new(x, 5)));
+        move_head(&mut world, left, IVec2::new(5, 5));
+        move_head(&mut world, right, IVec2::new(5, 5));
+
+        let found = contacts(&mut world);
+        assert_eq!(found.len(), 1);
+        let (biter, cell, kind) = found[0];
+        let other = if biter == left { right } else { left };
+        assert_eq!(cell, IVec2::new(5, 5));
+        assert_eq!(kind, CollisionKind::HeadOn { other });
+    }
+
+    #[test]
+    fn heads_swapping_cells_are_head_on() {
+        let mut world = world_with(default());
+        // each head has moved onto the other's old cell, and each neck has followed
+        let left = spawn_snake(&mut world, &[IVec2::new(6, 5), IVec2::new(5, 5)]);
+        let right = spawn_snake(&mut world, &[IVec2::new(5, 5), IVec2::new(6, 5)]);
+        let mut occupancy = world.resource_mut::<Occupancy>();
+        occupancy.set(IVec2::new(5, 5), Occupant::Body(left));
+        occupancy.set(IVec2::new(6, 5), Occupant::Body(right));
+
+        let found = contacts(&mut world);
+        assert_eq!(found.len(), 2);
+        for (biter, _, kind) in found {
+            let other = if biter == left { right } else { left };
+            assert_eq!(kind, CollisionKind::HeadOn { other });
+        }
+    }
+
+    #[test]
+    fn biter_dies_leaves_the_victim_whole() {
+        let (world, victim, biter) = body_bite(default(), 3);
+        assert_eq!(outcomes(&world), vec![CollisionOutcome::BiterDied]);
+        assert!(is_dead(&world, biter));
+        assert!(!is_dead(&world, victim));
+        assert_eq!(body_len(&world, victim), 4);
+    }
+
+    #[test]
+    fn both_die_kills_both_snakes() {
+        let (world, victim, biter) = body_bite(
+            CollisionRules {
+                body_bite: CollisionRule::BothDie,
+                ..default()
+            },
+            3,
+        );
+        assert_eq!(outcomes(&world), vec![CollisionOutcome::BothDied]);
+        assert!(is_dead(&world, biter));
+        assert!(is_dead(&world, victim));
+    }
+
+    #[test]
+    fn longer_wins_kills_the_shorter_snake() {
+        let rules = CollisionRules {
+            body_bite: CollisionRule::LongerWins,
+            ..default()
+        };
+        let (world, victim, biter) = body_bite(rules.clone(), 7);
+        assert_eq!(outcomes(&world), vec![CollisionOutcome::VictimDied]);
+        assert!(is_dead(&world, victim));
+        assert!(!is_dead(&world, biter));
+
+        let (world, victim, biter) = body_bite(rules, 3);
+        assert_eq!(outcomes(&world), vec![CollisionOutcome::BiterDied]);
+        assert!(is_dead(&world, biter));
+        assert!(!is_dead(&world, victim));
+    }
+
+    #[test]
+    fn cut_at_contact_only_shortens_the_victim() {
+        let (world, victim, biter) = body_bite(
+            CollisionRules {
+                body_bite: CollisionRule::CutAtContact,
+                ..default()
+            },
+            3,
+        );
+        assert_eq!(
+            outcomes(&world),
+            vec![CollisionOutcome::Cut { segments: 3 }]
+        );
+        assert_eq!(body_len(&world, victim), 1);
+        assert_eq!(body_len(&world, biter), 2);
+        assert_eq!(
+            world.resource::<Occupancy>().get(IVec2::new(8, 5)),
+            Some(Occupant::Head(biter))
+        );
+    }
+
+    #[test]
+    fn head_on_falls_back_to_both_dying_for_steal_and_cut() {
+        for rule in [CollisionRule::StealSegments, CollisionRule::CutAtContact] {
+            let mut world = world_with(CollisionRules {
+                head_on: rule,
+                ..default()
+            });
+            let left = spawn_snake(&mut world, &row(4, 5, 3));
+            let right = spawn_snake(&mut world, &[6, 7, 8].map(|x| IVec2::new(x, 5)));
+            move_head(&mut world, left, IVec2::new(5, 5));
+            move_head(&mut world, right, IVec2::new(5, 5));
+            tick(&mut world);
+
+            assert_eq!(outcomes(&world), vec![CollisionOutcome::BothDied]);
+            assert!(is_dead(&world, left) && is_dead(&world, right));
+        }
+    }
+
+    #[test]
+    fn stealing_grows_the_biter_by_every_stolen_segment() {
+        let mut world = world_with(CollisionRules {
+            body_bite: CollisionRule::StealSegments,
+            ..default()
+        });
+        let victim = spawn_snake(&mut world, &row(10, 5, 5));
+        let biter = spawn_snake(&mut world, &row(3, 3, 3));
+        move_head(&mut world, victim, IVec2::new(11, 5));
+        move_head(&mut world, biter, IVec2::new(8, 5));
+        tick(&mut world);
+
+        assert_eq!(body_len(&world, victim), 1);
+        assert_eq!(body_len(&world, biter), 2 + 3);
+        assert_eq!(
+            outcomes(&world),
+            vec![CollisionOutcome::Stolen { segments: 3 }]
+        );
+        let body = &world.get::<BodyRef>(biter).unwrap().0;
+        for part in body.iter() {
+            assert!(world.get_entity(*part).is_some());
+        }
+        let tails: Vec<Entity> = body
+            .iter()
+            .copied()
+            .filter(|part| world.get::<Tail>(*part).is_some())
+            .collect();
+        assert_eq!(tails, vec![*body.last().unwrap()]);
+    }
+}
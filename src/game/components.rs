@@ -6,11 +6,16 @@ use super::prelude::SnakeDirection;
 #[derive(Resource)]
 pub struct Paused(pub bool);
 
+/// Ticks played so far in the current match.
+#[derive(Resource, Default)]
+pub struct ElapsedTicks(pub u32);
+
 #[derive(Component)]
 pub struct ExampleGameText;
 
 #[derive(Component)]
-pub struct PausedText;
+pub struct DebugText;
+
 #[derive(Component)]
 pub struct ScoreText;
 
@@ -20,6 +25,25 @@ pub struct GameEntityRef(pub Entity);
 #[derive(Component)]
 pub struct Dead;
 
+/// The systems that set up the board when a match starts.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemSet)]
+pub struct MatchSetup;
+
+/// Everything that runs on a fixed tick of a match.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemSet)]
+pub struct MatchTick;
+
+/**
+ * The steps of a match tick. Whatever decides the turns the snakes take runs in `Input`,
+ * the rules in `Simulation` and whether that ended the round or match in `Outcome`.
+ */
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemSet)]
+pub enum TickSet {
+    Input,
+    Simulation,
+    Outcome,
+}
+
 #[derive(Debug, Hash, PartialEq, Eq, Clone, SystemSet)]
 pub enum PhysicsSet {
     Movement,
@@ -32,12 +56,6 @@ pub struct Vel(pub Vec2);
 #[derive(Debug, Component, From)]
 pub struct Pos(pub Vec2);
 
-#[derive(Debug, Component, From)]
-/**
- * The Bounding component is used to represent the radius of an entity in pixels.
- */
-pub struct Bounding(pub f32);
-
 #[derive(Component)]
 pub struct SnakeHead {
     pub direction: SnakeDirection,
@@ -49,12 +67,20 @@ pub struct SnakeBodyPart;
 #[derive(Event)]
 pub struct MoveAppleEvent;
 
+/// Grows the snake with the given head by a number of segments at its tail.
 #[derive(Event)]
-pub struct GrowSnakeEvent(pub Entity);
+pub struct GrowSnakeEvent {
+    pub snake: Entity,
+    pub segments: usize,
+}
 
 #[derive(Component)]
 pub struct Apple;
 
+/// An extra apple, left by a dead snake. It is gone once eaten instead of moving elsewhere.
+#[derive(Component)]
+pub struct BonusApple;
+
 #[derive(Component)]
 pub struct Tail;
 
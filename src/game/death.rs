@@ -0,0 +1,154 @@
+use bevy::prelude::*;
+
+use crate::GameState;
+
+use super::components::{
+    Apple, BonusApple, Collidible, Dead, GameEntityRef, ScoreText, SnakeBodyPart, SnakeHead,
+};
+use super::effects::{FadeOut, Flick, TimedRemoval};
+use super::grid::GridPos;
+use super::occupancy::{Occupancy, Occupant};
+use super::prelude::{BodyRef, Player, SnakeHeadRef};
+use super::rules::{DeadBodies, GameRules};
+
+/// How long a dead snake takes to fade away.
+const FADE_SECONDS: f32 = 1.5;
+/// How long the notice of a snake going out stays up.
+const OUT_NOTICE_SECONDS: f32 = 2.;
+const OUT_COLOR: Color = Color::srgb(0.5, 0.5, 0.5);
+
+/// The heads of the snakes that died this tick.
+pub type NewlyDead<'w, 's, D> = Query<'w, 's, D, (With<SnakeHead>, Added<Dead>)>;
+
+/**
+ * Clears what is left of the snakes that died this tick off the board, as the rules say. Their
+ * entities stay around until the match ends so the stats and results can still refer to them.
+ */
+pub fn dispose_dead_bodies(
+    mut commands: Commands,
+    rules: Res<GameRules>,
+    mut occupancy: ResMut<Occupancy>,
+    heads: NewlyDead<(Entity, &GridPos, &BodyRef)>,
+    body_parts: Query<&GridPos, With<SnakeBodyPart>>,
+) {
+    if rules.dead_bodies == DeadBodies::Remain {
+        return;
+    }
+    for (head, head_pos, body_ref) in heads.iter() {
+        let cells = std::iter::once((head_pos.0, Occupant::Head(head))).chain(
+            body_ref
+                .0
+                .iter()
+                .filter_map(|part| body_parts.get(*part).ok())
+                .map(|pos| (pos.0, Occupant::Body(head))),
+        );
+        for (cell, occupant) in cells.collect::<Vec<_>>() {
+            match rules.dead_bodies {
+                DeadBodies::Fade => occupancy.clear_if(cell, occupant),
+                // a cell the snake died on top of another snake is left to that snake
+                DeadBodies::Apples if occupancy.get(cell) == Some(occupant) => {
+                    let apple = commands
+                        .spawn((
+                            Apple,
+                            BonusApple,
+                            GridPos(cell),
+                            StateScoped(GameState::InGame),
+                        ))
+                        .id();
+                    occupancy.set(cell, Occupant::Apple(apple));
+                }
+                DeadBodies::Apples | DeadBodies::Remain => {}
+            }
+        }
+        for part in std::iter::once(head).chain(body_ref.0.iter().copied()) {
+            commands.entity(part).remove::<Collidible>();
+        }
+    }
+}
+
+/**
+ * Fades out or hides the sprites of a snake that just died, unless its skeleton is left on the
+ * board.
+ */
+pub fn clear_dead_sprites(
+    mut commands: Commands,
+    rules: Res<GameRules>,
+    heads: NewlyDead<(Entity, &BodyRef)>,
+    mut visibilities: Query<&mut Visibility>,
+) {
+    for (head, body_ref) in heads.iter() {
+        for part in std::iter::once(head).chain(body_ref.0.iter().copied()) {
+            let mut entity = commands.entity(part);
+            entity.remove::<Flick>();
+            match rules.dead_bodies {
+                DeadBodies::Remain => {}
+                DeadBodies::Fade => {
+                    entity.insert(FadeOut(Timer::from_seconds(FADE_SECONDS, TimerMode::Once)));
+                }
+                // the apples take its place straight away
+                DeadBodies::Apples => {
+                    if let Ok(mut visibility) = visibilities.get_mut(part) {
+                        *visibility = Visibility::Hidden;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/**
+ * Tells everyone which player just went out, and greys out its score for the rest of the match.
+ */
+pub fn announce_out(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    heads: NewlyDead<Entity>,
+    players: Query<(Entity, &SnakeHeadRef), With<Player>>,
+    mut score_texts: Query<(&mut Text, &GameEntityRef), With<ScoreText>>,
+) {
+    let mut players: Vec<_> = players.iter().collect();
+    players.sort_by_key(|(player, _)| *player);
+
+    for (shown, head) in heads.iter().enumerate() {
+        let Some(index) = players
+            .iter()
+            .position(|(_, head_ref)| head_ref.0 == Some(head))
+        else {
+            continue;
+        };
+        let player = players[index].0;
+
+        for (mut text, ge_ref) in score_texts.iter_mut() {
+            if ge_ref.0 == player {
+                text.sections[0].style.color = OUT_COLOR;
+            }
+        }
+        // with a single player the results say it all
+        if players.len() < 2 {
+            continue;
+        }
+        commands.spawn((
+            TextBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Percent(15. + 8. * shown as f32),
+                    width: Val::Percent(100.),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                text: Text::from_section(
+                    format!("P{} OUT", index + 1),
+                    TextStyle {
+                        font_size: 50.,
+                        color: Color::WHITE,
+                        font: asset_server.load("fonts/visitor.ttf"),
+                    },
+                )
+                .with_justify(JustifyText::Center),
+                ..default()
+            },
+            TimedRemoval(Timer::from_seconds(OUT_NOTICE_SECONDS, TimerMode::Once)),
+            StateScoped(GameState::InGame),
+        ));
+    }
+}
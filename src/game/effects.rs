@@ -21,6 +21,10 @@ pub struct Shrink(pub Timer);
 #[derive(Debug, Component)]
 pub struct DelayedVisibility(pub Timer);
 
+/// Fades a sprite out over the timer, and hides it once it is done.
+#[derive(Debug, Component)]
+pub struct FadeOut(pub Timer);
+
 pub fn flick_system(
     mut commands: Commands,
     mut query: Query<(Entity, &mut Visibility, &mut Flick)>,
@@ -55,7 +59,7 @@ pub fn _shrink_system(mut shrinking: Query<(&mut Transform, &mut Shrink)>, time:
     }
 }
 
-pub fn _timed_removal_system(
+pub fn timed_removal_system(
     mut commands: Commands,
     time: Res<Time>,
     mut query: Query<(Entity, &mut TimedRemoval), Without<DelayedVisibility>>,
@@ -83,3 +87,19 @@ pub fn _delayed_visibility_system(
         }
     }
 }
+
+pub fn fade_out_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut FadeOut, &mut Sprite, &mut Visibility)>,
+) {
+    for (entity, mut fade, mut sprite, mut visibility) in query.iter_mut() {
+        fade.0.tick(time.delta());
+        sprite.color.set_alpha(1. - fade.0.fraction());
+
+        if fade.0.finished() {
+            *visibility = Visibility::Hidden;
+            commands.entity(entity).remove::<FadeOut>();
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Shutdown, TcpStream},
+    process::{Child, Command, Stdio},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::brain::{BoardSnapshot, SnakeBrain};
+use super::grid::ArenaMode;
+use super::prelude::SnakeDirection;
+
+/// How long a bot gets to answer a tick unless told otherwise with `--bot-budget`.
+pub const DEFAULT_BOT_BUDGET: Duration = Duration::from_millis(50);
+/// How long a bot program gets to exit by itself once its stdin is closed.
+const EXIT_GRACE: Duration = Duration::from_millis(200);
+
+type BotReader = Box<dyn Read + Send>;
+type BotWriter = Box<dyn Write + Send + Sync>;
+
+#[derive(Serialize)]
+struct ExternalSnake<'a> {
+    name: &'a str,
+    /// Head first.
+    cells: Vec<[i32; 2]>,
+    direction: char,
+    alive: bool,
+    score: u32,
+}
+
+/**
+ * The board as sent to a bot every tick, as a single line of JSON. Cells are `[x, y]` with
+ * `[0, 0]` the bottom left corner, directions are `U`, `D`, `L` or `R`.
+ */
+#[derive(Serialize)]
+struct ExternalBoard<'a> {
+    tick: u32,
+    /// The index of the bot's own snake in `snakes`.
+    you: usize,
+    budget_ms: u64,
+    width: i32,
+    height: i32,
+    arena_mode: ArenaMode,
+    walls: Vec<[i32; 2]>,
+    /// Pairs of portal ends, each leading to the other.
+    portals: Vec<[[i32; 2]; 2]>,
+    apples: Vec<[i32; 2]>,
+    snakes: Vec<ExternalSnake<'a>>,
+}
+
+/// A bot's answer, a single line of JSON such as `{"tick": 12, "direction": "L"}`.
+#[derive(Deserialize)]
+struct ExternalMove {
+    tick: u32,
+    direction: char,
+}
+
+/**
+ * A bot running outside the game, in any language. It is sent the board every tick and has
+ * `budget` to answer with its move, or the snake carries on in the direction it was going.
+ * Bots are picked with `exec:COMMAND` to start a program and talk to it over stdin and stdout,
+ * or `tcp:ADDRESS` to connect to one that is already listening on a local socket.
+ */
+pub struct ExternalBrain {
+    spec: String,
+    writer: Option<BotWriter>,
+    moves: Mutex<Receiver<ExternalMove>>,
+    child: Option<Child>,
+    /// The connection to a `tcp:` bot, which the thread reading its answers holds on to as well.
+    socket: Option<TcpStream>,
+    budget: Duration,
+    /// When the answer to the board sent by `prepare` is due.
+    deadline: Option<Instant>,
+}
+
+impl ExternalBrain {
+    /// Whether `spec` names an external bot rather than a registered one.
+    pub fn is_spec(spec: &str) -> bool {
+        spec.starts_with("exec:") || spec.starts_with("tcp:")
+    }
+
+    pub fn open(spec: &str, budget: Duration) -> io::Result<Self> {
+        let (reader, writer, child, socket): (BotReader, BotWriter, _, _) =
+            if let Some(command) = spec.strip_prefix("exec:") {
+                let mut parts = command.split_whitespace();
+                let program = parts
+                    .next()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty command"))?;
+                let mut child = Command::new(program)
+                    .args(parts)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::inherit())
+                    .spawn()?;
+                let stdout = child.stdout.take().expect("stdout is piped");
+                let stdin = child.stdin.take().expect("stdin is piped");
+                (Box::new(stdout), Box::new(stdin), Some(child), None)
+            } else if let Some(addr) = spec.strip_prefix("tcp:") {
+                let stream = TcpStream::connect(addr)?;
+                stream.set_nodelay(true)?;
+                let socket = stream.try_clone()?;
+                (
+                    Box::new(stream.try_clone()?),
+                    Box::new(stream),
+                    None,
+                    Some(socket),
+                )
+            } else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "external bots start with exec: or tcp:",
+                ));
+            };
+
+        // answers are read on their own thread so a slow bot can be given up on
+        let (sender, moves) = mpsc::channel();
+        let name = spec.to_string();
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                match serde_json::from_str(&line) {
+                    Ok(answer) => {
+                        if sender.send(answer).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => warn!("{name} sent a bad move: {err}"),
+                }
+            }
+        });
+
+        info!("started bot {spec}");
+        Ok(ExternalBrain {
+            spec: spec.to_string(),
+            writer: Some(writer),
+            moves: Mutex::new(moves),
+            child,
+            socket,
+            budget,
+            deadline: None,
+        })
+    }
+
+    fn send_board(&mut self, board: &BoardSnapshot, me: usize) -> io::Result<()> {
+        let cell = |pos: &bevy::math::IVec2| pos.to_array();
+        let message = ExternalBoard {
+            tick: board.tick,
+            you: me,
+            budget_ms: self.budget.as_millis() as u64,
+            width: board.grid.width,
+            height: board.grid.height,
+            arena_mode: board.grid.mode,
+            walls: board.walls.iter().map(cell).collect(),
+            portals: board
+                .portals
+                .iter()
+                // every pair is in the map both ways round
+                .filter(|(from, to)| (from.x, from.y) < (to.x, to.y))
+                .map(|(from, to)| [cell(from), cell(to)])
+                .collect(),
+            apples: board.apples.iter().map(cell).collect(),
+            snakes: board
+                .snakes
+                .iter()
+                .map(|snake| ExternalSnake {
+                    name: &snake.name,
+                    cells: snake.cells.iter().map(cell).collect(),
+                    direction: snake.direction.to_char(),
+                    alive: snake.alive,
+                    score: snake.score,
+                })
+                .collect(),
+        };
+
+        let Some(writer) = self.writer.as_mut() else {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        };
+        let mut line = serde_json::to_vec(&message)?;
+        line.push(b'\n');
+        writer.write_all(&line)?;
+        writer.flush()
+    }
+
+    /// The bot's move for `tick` by `deadline`, skipping late answers to earlier ticks.
+    fn wait_for_move(&self, tick: u32, deadline: Instant) -> Option<SnakeDirection> {
+        let moves = self.moves.lock().ok()?;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match moves.recv_timeout(left) {
+                Ok(answer) if answer.tick == tick => {
+                    return SnakeDirection::from_char(answer.direction)
+                }
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) => {
+                    warn!("{} missed tick {tick}", self.spec);
+                    return None;
+                }
+                Err(RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+    }
+}
+
+impl SnakeBrain for ExternalBrain {
+    /// Sends the board without waiting, the budget counts from here.
+    fn prepare(&mut self, board: &BoardSnapshot, me: usize) {
+        self.deadline = None;
+        if self.writer.is_none() {
+            return;
+        }
+        match self.send_board(board, me) {
+            Ok(()) => self.deadline = Some(Instant::now() + self.budget),
+            Err(err) => {
+                warn!("lost bot {}: {err}", self.spec);
+                self.writer = None;
+            }
+        }
+    }
+
+    fn decide(&mut self, board: &BoardSnapshot, me: usize) -> SnakeDirection {
+        let moving = board.snakes[me].direction.clone();
+        // the board may not have been sent yet when not driven by `ai_steer`
+        if self.deadline.is_none() {
+            self.prepare(board, me);
+        }
+        match self.deadline.take() {
+            Some(deadline) => self.wait_for_move(board.tick, deadline).unwrap_or(moving),
+            None => moving,
+        }
+    }
+}
+
+impl Drop for ExternalBrain {
+    fn drop(&mut self) {
+        // closing stdin or the socket tells a well behaved bot to stop, the rest are killed
+        self.writer = None;
+        if let Some(socket) = self.socket.take() {
+            let _ = socket.shutdown(Shutdown::Write);
+        }
+        if let Some(child) = self.child.as_mut() {
+            let started = Instant::now();
+            while matches!(child.try_wait(), Ok(None)) && started.elapsed() < EXIT_GRACE {
+                thread::sleep(Duration::from_millis(5));
+            }
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use bevy::prelude::*;
+use derive_more::From;
+use serde::{Deserialize, Serialize};
+
+use super::{prelude::SnakeDirection, TILE_SIZE, WORLD_SIZE_X, WORLD_SIZE_Y};
+
+/**
+ * The authoritative position of a snake part or apple, in whole grid cells.
+ * `Transform` is derived from this by `sync_grid_transforms` and never read by the rules.
+ */
+#[derive(Debug, Component, Clone, Copy, PartialEq, Eq, Hash, Deref, DerefMut, From)]
+pub struct GridPos(pub IVec2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ArenaMode {
+    /// Leaving the grid is fatal.
+    #[default]
+    Walled,
+    /// Heads leaving the grid come back in on the opposite edge.
+    Wrap,
+}
+
+/**
+ * The play field. Cell (0, 0) is the bottom left corner and the grid is centered on the world origin.
+ */
+#[derive(Debug, Resource, Clone, Copy)]
+pub struct Grid {
+    pub width: i32,
+    pub height: i32,
+    pub tile_size: f32,
+    pub mode: ArenaMode,
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Grid {
+            width: WORLD_SIZE_X as i32,
+            height: WORLD_SIZE_Y as i32,
+            tile_size: TILE_SIZE,
+            mode: ArenaMode::default(),
+        }
+    }
+}
+
+impl Grid {
+    pub fn contains(&self, pos: IVec2) -> bool {
+        pos.x >= 0 && pos.x < self.width && pos.y >= 0 && pos.y < self.height
+    }
+
+    pub fn center(&self) -> IVec2 {
+        IVec2::new(self.width / 2, self.height / 2)
+    }
+
+    /// The cell one step away in `direction`, wrapped around the edges in `ArenaMode::Wrap`.
+    pub fn step(&self, pos: IVec2, direction: &SnakeDirection) -> IVec2 {
+        let next = pos + direction.delta();
+        match self.mode {
+            ArenaMode::Walled => next,
+            ArenaMode::Wrap => next.rem_euclid(IVec2::new(self.width, self.height)),
+        }
+    }
+
+    /// The shortest offset from one cell to another, going through the seam when wrapping.
+    pub fn offset(&self, from: IVec2, to: IVec2) -> IVec2 {
+        let delta = to - from;
+        match self.mode {
+            ArenaMode::Walled => delta,
+            ArenaMode::Wrap => {
+                let size = IVec2::new(self.width, self.height);
+                (delta + size / 2).rem_euclid(size) - size / 2
+            }
+        }
+    }
+
+    /// World space position of the middle of a cell.
+    pub fn to_world(self, pos: IVec2) -> Vec2 {
+        Vec2::new(
+            (pos.x as f32 - self.width as f32 / 2. + 0.5) * self.tile_size,
+            (pos.y as f32 - self.height as f32 / 2. + 0.5) * self.tile_size,
+        )
+    }
+}
+
+pub fn sync_grid_transforms(
+    grid: Res<Grid>,
+    mut query: Query<(&GridPos, &mut Transform), Changed<GridPos>>,
+) {
+    for (pos, mut transform) in query.iter_mut() {
+        transform.translation = grid.to_world(pos.0).extend(transform.translation.z);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrapping() -> Grid {
+        Grid {
+            mode: ArenaMode::Wrap,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn step_wraps_around_every_edge() {
+        let grid = wrapping();
+        let (right, top) = (grid.width - 1, grid.height - 1);
+        assert_eq!(
+            grid.step(IVec2::new(right, 5), &SnakeDirection::Right),
+            IVec2::new(0, 5)
+        );
+        assert_eq!(
+            grid.step(IVec2::new(0, 5), &SnakeDirection::Left),
+            IVec2::new(right, 5)
+        );
+        assert_eq!(
+            grid.step(IVec2::new(5, top), &SnakeDirection::Up),
+            IVec2::new(5, 0)
+        );
+        assert_eq!(
+            grid.step(IVec2::new(5, 0), &SnakeDirection::Down),
+            IVec2::new(5, top)
+        );
+    }
+
+    #[test]
+    fn step_leaves_a_walled_grid() {
+        let grid = Grid::default();
+        let next = grid.step(IVec2::new(grid.width - 1, 5), &SnakeDirection::Right);
+        assert_eq!(next, IVec2::new(grid.width, 5));
+        assert!(!grid.contains(next));
+    }
+
+    #[test]
+    fn offset_goes_through_the_seam_when_shorter() {
+        let grid = wrapping();
+        let (right, top) = (grid.width - 1, grid.height - 1);
+        assert_eq!(
+            grid.offset(IVec2::new(right, 5), IVec2::new(0, 5)),
+            IVec2::X
+        );
+        assert_eq!(
+            grid.offset(IVec2::new(0, 5), IVec2::new(right, 5)),
+            IVec2::NEG_X
+        );
+        assert_eq!(
+            grid.offset(IVec2::new(5, 0), IVec2::new(5, top)),
+            IVec2::NEG_Y
+        );
+        assert_eq!(
+            grid.offset(IVec2::new(2, 2), IVec2::new(5, 4)),
+            IVec2::new(3, 2)
+        );
+    }
+
+    #[test]
+    fn offset_never_wraps_in_a_walled_grid() {
+        let grid = Grid::default();
+        assert_eq!(
+            grid.offset(IVec2::new(grid.width - 1, 5), IVec2::new(0, 5)),
+            IVec2::new(1 - grid.width, 0)
+        );
+    }
+}
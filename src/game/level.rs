@@ -0,0 +1,411 @@
+use std::fmt;
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::HashMap,
+};
+use bevy_asset_loader::asset_collection::AssetCollection;
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::GameState;
+
+use super::grid::Grid;
+use super::occupancy::{Occupancy, Occupant};
+use super::rules::GameRules;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LevelTile {
+    Floor,
+    Wall,
+    /// Floor that apples prefer to spawn on.
+    AppleZone,
+    /// One end of a portal, paired with the other tile using the same letter.
+    Portal(char),
+}
+
+impl LevelTile {
+    fn texture_index(&self) -> u32 {
+        match self {
+            LevelTile::Floor => 0,
+            LevelTile::Wall => 1,
+            LevelTile::AppleZone => 2,
+            LevelTile::Portal(_) => 3,
+        }
+    }
+}
+
+/**
+ * An arena read from a `.level` file. Each line of the file is a row of tiles, top row first:
+ * `.` floor, `#` wall, `*` apple spawn zone, `1`-`4` player spawn points (heading right)
+ * and `A`-`Z` portals, which must come in pairs. An optional first line `name: ...` names the arena.
+ */
+#[derive(Asset, TypePath, Debug, Clone)]
+pub struct Level {
+    pub name: String,
+    pub width: i32,
+    pub height: i32,
+    tiles: Vec<LevelTile>,
+    pub spawns: Vec<IVec2>,
+    pub portals: HashMap<IVec2, IVec2>,
+}
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(std::io::Error),
+    Empty,
+    RaggedRow(usize),
+    UnknownTile(char),
+    UnpairedPortal(char),
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelError::Io(err) => write!(f, "could not read level: {err}"),
+            LevelError::Empty => write!(f, "level has no rows"),
+            LevelError::RaggedRow(row) => write!(f, "row {row} has a different width"),
+            LevelError::UnknownTile(c) => write!(f, "unknown tile '{c}'"),
+            LevelError::UnpairedPortal(c) => write!(f, "portal '{c}' needs exactly two ends"),
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+impl From<std::io::Error> for LevelError {
+    fn from(err: std::io::Error) -> Self {
+        LevelError::Io(err)
+    }
+}
+
+impl Level {
+    /// A level without walls of the given size.
+    pub fn open(width: i32, height: i32) -> Self {
+        Level {
+            name: "Open".to_string(),
+            width,
+            height,
+            tiles: vec![LevelTile::Floor; (width * height) as usize],
+            spawns: Vec::new(),
+            portals: HashMap::new(),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, LevelError> {
+        let mut name = "Unnamed".to_string();
+        let mut rows = Vec::new();
+        for line in text.lines().map(str::trim_end) {
+            if let Some(value) = line.strip_prefix("name:") {
+                name = value.trim().to_string();
+            } else if !line.is_empty() {
+                rows.push(line);
+            }
+        }
+
+        let height = rows.len() as i32;
+        let width = rows.first().ok_or(LevelError::Empty)?.chars().count() as i32;
+        let mut tiles = vec![LevelTile::Floor; (width * height) as usize];
+        let mut spawns = Vec::new();
+        let mut portal_ends: HashMap<char, Vec<IVec2>> = HashMap::new();
+
+        for (row, line) in rows.iter().enumerate() {
+            if line.chars().count() as i32 != width {
+                return Err(LevelError::RaggedRow(row + 1));
+            }
+            // the first row of the file is the top of the arena
+            let y = height - 1 - row as i32;
+            for (x, c) in line.chars().enumerate() {
+                let pos = IVec2::new(x as i32, y);
+                let tile = match c {
+                    '.' => LevelTile::Floor,
+                    '#' => LevelTile::Wall,
+                    '*' => LevelTile::AppleZone,
+                    '1'..='4' => {
+                        spawns.push((c, pos));
+                        LevelTile::Floor
+                    }
+                    'A'..='Z' => {
+                        portal_ends.entry(c).or_default().push(pos);
+                        LevelTile::Portal(c)
+                    }
+                    _ => return Err(LevelError::UnknownTile(c)),
+                };
+                tiles[(y * width) as usize + x] = tile;
+            }
+        }
+
+        let mut portals = HashMap::new();
+        for (c, ends) in portal_ends {
+            let [a, b] = ends[..] else {
+                return Err(LevelError::UnpairedPortal(c));
+            };
+            portals.insert(a, b);
+            portals.insert(b, a);
+        }
+        spawns.sort_by_key(|(c, _)| *c);
+
+        Ok(Level {
+            name,
+            width,
+            height,
+            tiles,
+            spawns: spawns.into_iter().map(|(_, pos)| pos).collect(),
+            portals,
+        })
+    }
+
+    pub fn cells(&self) -> impl Iterator<Item = (IVec2, LevelTile)> + '_ {
+        let width = self.width;
+        self.tiles
+            .iter()
+            .enumerate()
+            .map(move |(i, tile)| (IVec2::new(i as i32 % width, i as i32 / width), *tile))
+    }
+}
+
+#[derive(Default)]
+pub struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    type Asset = Level;
+    type Settings = ();
+    type Error = LevelError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Level, LevelError> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text).await?;
+        Level::parse(&text)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level"]
+    }
+}
+
+#[derive(AssetCollection, Resource)]
+pub struct LevelAssets {
+    #[asset(
+        paths("levels/box.level", "levels/cross.level", "levels/portals.level"),
+        collection(typed)
+    )]
+    pub levels: Vec<Handle<Level>>,
+}
+
+/**
+ * The arena the next match is played in. 0 is the open arena sized by the game rules,
+ * anything above picks from `LevelAssets::levels`.
+ */
+#[derive(Resource, Default)]
+pub struct SelectedLevel(pub usize);
+
+impl SelectedLevel {
+    pub fn level(
+        &self,
+        level_assets: Option<&LevelAssets>,
+        levels: &Assets<Level>,
+        rules: &GameRules,
+    ) -> Level {
+        self.0
+            .checked_sub(1)
+            .and_then(|i| level_assets?.levels.get(i))
+            .and_then(|handle| levels.get(handle))
+            .cloned()
+            .unwrap_or_else(|| Level::open(rules.arena_width, rules.arena_height))
+    }
+
+    pub fn next(&mut self, level_assets: &LevelAssets) {
+        self.0 = (self.0 + 1) % (level_assets.levels.len() + 1);
+    }
+}
+
+/// The arena of the running match.
+#[derive(Resource, Deref)]
+pub struct ActiveLevel(pub Level);
+
+impl FromWorld for ActiveLevel {
+    fn from_world(world: &mut World) -> Self {
+        let grid = world.resource::<Grid>();
+        ActiveLevel(Level::open(grid.width, grid.height))
+    }
+}
+
+/**
+ * Picks the selected arena for the match, sizes the grid after it and walls off its wall tiles.
+ */
+pub fn load_level(
+    selected: Res<SelectedLevel>,
+    level_assets: Option<Res<LevelAssets>>,
+    levels: Res<Assets<Level>>,
+    rules: Res<GameRules>,
+    mut grid: ResMut<Grid>,
+    mut occupancy: ResMut<Occupancy>,
+    mut active_level: ResMut<ActiveLevel>,
+) {
+    let level = selected.level(level_assets.as_deref(), &levels, &rules);
+
+    grid.width = level.width;
+    grid.height = level.height;
+    grid.tile_size = rules.tile_size;
+    *occupancy = Occupancy::new(&grid);
+    for (pos, tile) in level.cells() {
+        if tile == LevelTile::Wall {
+            occupancy.set(pos, Occupant::Wall);
+        }
+    }
+    active_level.0 = level;
+}
+
+pub fn spawn_level_tilemap(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    grid: Res<Grid>,
+    level: Res<ActiveLevel>,
+) {
+    spawn_tilemap(
+        &mut commands,
+        asset_server.load("textures/tiles.png"),
+        &grid,
+        &level,
+        || StateScoped(GameState::InGame),
+    );
+}
+
+/**
+ * Spawns the tiles of `level` laid over `grid`. Every spawned entity also gets the bundle made by
+ * `scope`, which decides when the tilemap goes away again.
+ */
+pub fn spawn_tilemap<B: Bundle>(
+    commands: &mut Commands,
+    texture: Handle<Image>,
+    grid: &Grid,
+    level: &Level,
+    scope: impl Fn() -> B,
+) {
+    let map_size = TilemapSize {
+        x: level.width as u32,
+        y: level.height as u32,
+    };
+    let tilemap_entity = commands.spawn_empty().id();
+    let mut tile_storage = TileStorage::empty(map_size);
+
+    for (pos, tile) in level.cells() {
+        let tile_pos = TilePos {
+            x: pos.x as u32,
+            y: pos.y as u32,
+        };
+        let tile_entity = commands
+            .spawn((
+                TileBundle {
+                    position: tile_pos,
+                    tilemap_id: TilemapId(tilemap_entity),
+                    texture_index: TileTextureIndex(tile.texture_index()),
+                    ..Default::default()
+                },
+                scope(),
+            ))
+            .id();
+        tile_storage.set(&tile_pos, tile_entity);
+    }
+
+    // 16px tiles scaled up to the grid's tile size, with tile (0, 0) on the first grid cell
+    let tile_size = TilemapTileSize { x: 16., y: 16. };
+    commands.entity(tilemap_entity).insert((
+        TilemapBundle {
+            grid_size: tile_size.into(),
+            map_type: TilemapType::Square,
+            size: map_size,
+            storage: tile_storage,
+            texture: TilemapTexture::Single(texture),
+            tile_size,
+            transform: Transform::from_translation(grid.to_world(IVec2::ZERO).extend(-1.))
+                .with_scale(Vec3::splat(grid.tile_size / tile_size.x)),
+            ..Default::default()
+        },
+        scope(),
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile(level: &Level, x: i32, y: i32) -> LevelTile {
+        level.tiles[(y * level.width + x) as usize]
+    }
+
+    #[test]
+    fn first_row_is_the_top_of_the_arena() {
+        let level = Level::parse("name: Test\n#.*\n..#\n").unwrap();
+
+        assert_eq!(level.name, "Test");
+        assert_eq!((level.width, level.height), (3, 2));
+        assert_eq!(tile(&level, 0, 1), LevelTile::Wall);
+        assert_eq!(tile(&level, 1, 1), LevelTile::Floor);
+        assert_eq!(tile(&level, 2, 1), LevelTile::AppleZone);
+        assert_eq!(tile(&level, 2, 0), LevelTile::Wall);
+        assert_eq!(
+            level
+                .cells()
+                .find(|(_, tile)| *tile == LevelTile::AppleZone),
+            Some((IVec2::new(2, 1), LevelTile::AppleZone))
+        );
+    }
+
+    #[test]
+    fn spawns_are_floor_in_player_order() {
+        let level = Level::parse("2..\n..1\n").unwrap();
+
+        assert_eq!(level.name, "Unnamed");
+        assert_eq!(level.spawns, vec![IVec2::new(2, 0), IVec2::new(0, 1)]);
+        assert_eq!(tile(&level, 0, 1), LevelTile::Floor);
+    }
+
+    #[test]
+    fn portal_ends_lead_to_each_other() {
+        let level = Level::parse("A.B\nB.A\n").unwrap();
+
+        assert_eq!(tile(&level, 0, 1), LevelTile::Portal('A'));
+        assert_eq!(level.portals.len(), 4);
+        assert_eq!(level.portals[&IVec2::new(0, 1)], IVec2::new(2, 0));
+        assert_eq!(level.portals[&IVec2::new(2, 0)], IVec2::new(0, 1));
+        assert_eq!(level.portals[&IVec2::new(2, 1)], IVec2::new(0, 0));
+    }
+
+    #[test]
+    fn portals_need_exactly_two_ends() {
+        assert!(matches!(
+            Level::parse("A..\n...\n"),
+            Err(LevelError::UnpairedPortal('A'))
+        ));
+        assert!(matches!(
+            Level::parse("A.A\n..A\n"),
+            Err(LevelError::UnpairedPortal('A'))
+        ));
+    }
+
+    #[test]
+    fn bad_levels_are_rejected() {
+        assert!(matches!(
+            Level::parse("...\n..\n"),
+            Err(LevelError::RaggedRow(2))
+        ));
+        assert!(matches!(
+            Level::parse("name: Nothing\n\n"),
+            Err(LevelError::Empty)
+        ));
+        assert!(matches!(
+            Level::parse("..?\n"),
+            Err(LevelError::UnknownTile('?'))
+        ));
+        assert!(matches!(
+            Level::parse("..5\n"),
+            Err(LevelError::UnknownTile('5'))
+        ));
+    }
+}
@@ -1,63 +1,221 @@
 use self::{
-    components::{Paused, PhysicsSet},
-    effects::flick_system,
+    ai::{ai_steer, AiController},
+    brain::BrainRegistry,
+    components::{ElapsedTicks, MatchSetup, MatchTick, Paused, PhysicsSet, TickSet},
+    effects::{fade_out_system, flick_system, timed_removal_system},
     systems::{example_update, game_keys, pause_controls, setup_players},
 };
-use crate::{GamePhase, GameState};
+use crate::{
+    config::Debug, controls::ControlsScreen, high_scores::PendingRecords, netplay::NetSession,
+    GamePhase, GameState,
+};
 use bevy::prelude::*;
+use bevy_common_assets::json::JsonAssetPlugin;
+use bevy_ecs_tilemap::TilemapPlugin;
+use collision::{resolve_collisions, CollisionEvent};
 use components::{GrowSnakeEvent, MoveAppleEvent};
+use death::{announce_out, clear_dead_sprites, dispose_dead_bodies};
+use grid::{sync_grid_transforms, Grid};
+use level::{load_level, spawn_level_tilemap, ActiveLevel, Level, LevelLoader, SelectedLevel};
+use occupancy::Occupancy;
+use replay::{Playback, ReplayPlugin};
+use results::{results_input, spawn_results};
+use rounds::{
+    check_round_over, end_versus_match, spawn_round_over, start_next_round, start_versus_match,
+    NextRound, VersusMatch,
+};
+use rules::{sync_game_rules, GameRules};
+use spawner::BoardFull;
+use sprites::{attach_sprites, show_skeletons, GameSprites};
+use stats::{track_deaths, track_survival};
 use systems::{
-    check_all_dead, check_apple_collision, check_death_collision, dead_controls, dead_text,
-    grow_snake, init_game, move_apple_handler, move_snakes, tear_down_players, update_score_text,
+    advance_seed, check_all_dead, check_apple_collision, count_tick, debug_overlay,
+    flick_on_collision, grow_snake, init_game, init_match, move_apple_handler, move_snakes,
+    orient_snakes, tear_down_players, update_score_text,
 };
 
+pub mod ai;
+pub mod brain;
 mod collision;
-mod components;
+pub mod components;
+mod death;
 mod effects;
+pub mod external;
+pub mod grid;
+pub mod level;
+mod occupancy;
 pub mod prelude;
+pub mod protocol;
+pub mod remote;
+pub mod replay;
+mod results;
+pub mod rounds;
+pub mod rules;
+mod spawner;
+pub mod sprites;
+pub mod stats;
 mod systems;
 
 pub const INITIAL_GAME_SPEED: f64 = 8.0;
+pub const TILE_SIZE: f32 = 32.;
+pub const WORLD_SIZE_X: u32 = 40;
+pub const WORLD_SIZE_Y: u32 = 22;
+// pub const TILE_SIZE: f32 = 16.;
+// pub const WORLD_SIZE_X: u32 = 80;
+// pub const WORLD_SIZE_Y: u32 = 45;
+
+/**
+ * The rules of the game: movement, growth, collisions and scoring. Needs no window,
+ * renderer or input so matches can also be run headless.
+ */
+pub struct SimulationPlugin;
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(JsonAssetPlugin::<GameRules>::new(&["rules.json"]))
+            .init_asset::<Level>()
+            .init_asset_loader::<LevelLoader>()
+            .add_event::<MoveAppleEvent>()
+            .add_event::<GrowSnakeEvent>()
+            .add_event::<CollisionEvent>()
+            // SETUP
+            .add_systems(
+                OnEnter(GameState::InGame),
+                (load_level, init_match, setup_players, move_apple_handler)
+                    .chain()
+                    .in_set(MatchSetup),
+            )
+            .add_systems(
+                OnExit(GameState::InGame),
+                tear_down_players.run_if(not(resource_exists::<NextRound>)),
+            )
+            .add_systems(
+                FixedUpdate,
+                ai_steer.in_set(TickSet::Input).run_if(
+                    in_state(GamePhase::Playing).and_then(any_with_component::<AiController>),
+                ),
+            )
+            // Playing state
+            .add_systems(
+                FixedUpdate,
+                (
+                    move_snakes,
+                    resolve_collisions,
+                    dispose_dead_bodies,
+                    check_apple_collision,
+                    move_apple_handler,
+                    grow_snake,
+                    (track_deaths, track_survival),
+                    count_tick,
+                )
+                    .chain()
+                    .in_set(TickSet::Simulation)
+                    .run_if(in_state(GamePhase::Playing)),
+            )
+            .add_systems(
+                FixedUpdate,
+                check_all_dead.in_set(TickSet::Outcome).run_if(
+                    in_state(GamePhase::Playing).and_then(not(resource_exists::<VersusMatch>)),
+                ),
+            )
+            .configure_sets(
+                FixedUpdate,
+                (TickSet::Input, TickSet::Simulation, TickSet::Outcome)
+                    .chain()
+                    .in_set(MatchTick),
+            )
+            .configure_sets(FixedUpdate, MatchTick.run_if(phase_settled))
+            .add_systems(
+                Update,
+                // replays and online matches play by the rules they were started with
+                sync_game_rules.run_if(not(
+                    resource_exists::<Playback>.or_else(resource_exists::<NetSession>)
+                )),
+            )
+            .init_resource::<Grid>()
+            .init_resource::<Occupancy>()
+            .init_resource::<SelectedLevel>()
+            .init_resource::<ActiveLevel>()
+            .init_resource::<GameRules>()
+            .init_resource::<BoardFull>()
+            .init_resource::<ElapsedTicks>()
+            .init_resource::<BrainRegistry>()
+            .insert_resource(Time::<Fixed>::from_hz(INITIAL_GAME_SPEED));
+    }
+}
+
+/**
+ * Whether no phase change is waiting to be applied. The tick that ends a round or match
+ * leaves its change pending until the next frame, and a frame that catches up on several
+ * ticks must not simulate past it.
+ */
+fn phase_settled(next_phase: Option<Res<NextState<GamePhase>>>) -> bool {
+    !matches!(next_phase.as_deref(), Some(NextState::Pending(_)))
+}
+
 pub struct GamePlugin;
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<MoveAppleEvent>()
-            .add_event::<GrowSnakeEvent>()
+        app.add_plugins((SimulationPlugin, TilemapPlugin, ReplayPlugin))
+            .init_resource::<GameSprites>()
             // SETUP
             .add_systems(
                 OnEnter(GameState::InGame),
-                (init_game, setup_players, move_apple_handler),
+                (
+                    spawn_level_tilemap,
+                    init_game,
+                    debug_overlay.run_if(|debug: Res<Debug>| debug.0),
+                    start_versus_match,
+                )
+                    .after(MatchSetup),
+            )
+            .add_systems(
+                OnExit(GameState::InGame),
+                (
+                    end_versus_match.run_if(not(resource_exists::<NextRound>)),
+                    advance_seed.run_if(not(resource_exists::<Playback>)),
+                ),
             )
-            .add_systems(OnExit(GameState::InGame), (tear_down_players))
             // Playing state
             .add_systems(
                 FixedUpdate,
-                (move_snakes, grow_snake, update_score_text).run_if(in_state(GamePhase::Playing)),
+                (
+                    update_score_text.run_if(in_state(GamePhase::Playing)),
+                    check_round_over.in_set(TickSet::Outcome).run_if(
+                        in_state(GamePhase::Playing).and_then(resource_exists::<VersusMatch>),
+                    ),
+                ),
             )
             .add_systems(
                 Update,
                 (
-                    pause_controls.run_if(in_state(GameState::InGame)),
-                    (
-                        check_all_dead,
-                        check_death_collision,
-                        check_apple_collision,
-                        example_update,
-                        game_keys,
-                        flick_system,
-                        move_apple_handler,
-                    )
+                    // a lockstep match can't stop on one side alone
+                    pause_controls
+                        .run_if(in_state(GameState::InGame))
+                        .run_if(in_state(ControlsScreen::Closed))
+                        .run_if(not(resource_exists::<NetSession>)),
+                    (attach_sprites, sync_grid_transforms, orient_snakes)
+                        .chain()
+                        .run_if(in_state(GameState::InGame)),
+                    (show_skeletons, clear_dead_sprites, announce_out)
+                        .after(attach_sprites)
+                        .run_if(in_state(GameState::InGame)),
+                    (fade_out_system, timed_removal_system).run_if(in_state(GameState::InGame)),
+                    (example_update, game_keys, flick_system, flick_on_collision)
                         .run_if(in_state(GamePhase::Playing)),
-                    (dead_controls).run_if(in_state(GamePhase::Dead)),
+                    start_next_round.run_if(in_state(GamePhase::RoundOver)),
+                    // a new high score is named before anything else
+                    results_input.run_if(
+                        in_state(GamePhase::Dead).and_then(not(resource_exists::<PendingRecords>)),
+                    ),
                 ),
             )
+            .add_systems(OnEnter(GamePhase::RoundOver), spawn_round_over)
             // Dead state
-            .add_systems(OnEnter(GamePhase::Dead), (dead_text))
+            .add_systems(OnEnter(GamePhase::Dead), spawn_results)
             .configure_sets(
                 Update,
                 PhysicsSet::Movement.before(PhysicsSet::CollisionDetection),
             )
-            .insert_resource(Paused(false))
-            .insert_resource(Time::<Fixed>::from_hz(INITIAL_GAME_SPEED));
+            .insert_resource(Paused(false));
     }
 }
@@ -0,0 +1,77 @@
+use bevy::prelude::*;
+
+use super::grid::Grid;
+
+/**
+ * What owns a grid cell. Snake cells refer to the head entity of the snake they belong to.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Occupant {
+    Head(Entity),
+    Body(Entity),
+    Apple(Entity),
+    Wall,
+}
+
+/**
+ * A per-cell lookup of the play field, kept up to date by the systems that move things around
+ * so the rules never have to compare every head against every body part.
+ */
+#[derive(Debug, Resource)]
+pub struct Occupancy {
+    grid: Grid,
+    cells: Vec<Option<Occupant>>,
+}
+
+impl FromWorld for Occupancy {
+    fn from_world(world: &mut World) -> Self {
+        Occupancy::new(world.resource::<Grid>())
+    }
+}
+
+impl Occupancy {
+    pub fn new(grid: &Grid) -> Self {
+        Occupancy {
+            grid: *grid,
+            cells: vec![None; (grid.width * grid.height) as usize],
+        }
+    }
+
+    fn index(&self, pos: IVec2) -> Option<usize> {
+        if !self.grid.contains(pos) {
+            return None;
+        }
+        Some((pos.y * self.grid.width + pos.x) as usize)
+    }
+
+    /// Cells outside the grid are reported as walls.
+    pub fn get(&self, pos: IVec2) -> Option<Occupant> {
+        match self.index(pos) {
+            Some(i) => self.cells[i],
+            None => Some(Occupant::Wall),
+        }
+    }
+
+    pub fn is_free(&self, pos: IVec2) -> bool {
+        self.get(pos).is_none()
+    }
+
+    pub fn set(&mut self, pos: IVec2, occupant: Occupant) {
+        if let Some(i) = self.index(pos) {
+            self.cells[i] = Some(occupant);
+        }
+    }
+
+    pub fn clear(&mut self, pos: IVec2) {
+        if let Some(i) = self.index(pos) {
+            self.cells[i] = None;
+        }
+    }
+
+    /// Clears the cell only if it is still owned by `occupant`.
+    pub fn clear_if(&mut self, pos: IVec2, occupant: Occupant) {
+        if self.get(pos) == Some(occupant) {
+            self.clear(pos);
+        }
+    }
+}
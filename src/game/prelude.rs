@@ -1,5 +1,7 @@
+use std::collections::VecDeque;
+
 use bevy::{
-    prelude::{Bundle, Component, Entity, KeyCode},
+    prelude::{Bundle, Component, Entity, IVec2, KeyCode},
     utils::HashMap,
 };
 
@@ -11,6 +13,58 @@ pub enum SnakeDirection {
     Down,
 }
 
+impl SnakeDirection {
+    /// The cell offset of one step in this direction.
+    pub fn delta(&self) -> IVec2 {
+        match self {
+            SnakeDirection::Left => IVec2::NEG_X,
+            SnakeDirection::Right => IVec2::X,
+            SnakeDirection::Up => IVec2::Y,
+            SnakeDirection::Down => IVec2::NEG_Y,
+        }
+    }
+
+    pub fn opposite(&self) -> SnakeDirection {
+        match self {
+            SnakeDirection::Left => SnakeDirection::Right,
+            SnakeDirection::Right => SnakeDirection::Left,
+            SnakeDirection::Up => SnakeDirection::Down,
+            SnakeDirection::Down => SnakeDirection::Up,
+        }
+    }
+
+    /// A single letter for compact storage: `U`, `D`, `L` or `R`.
+    pub fn to_char(&self) -> char {
+        match self {
+            SnakeDirection::Up => 'U',
+            SnakeDirection::Down => 'D',
+            SnakeDirection::Left => 'L',
+            SnakeDirection::Right => 'R',
+        }
+    }
+
+    pub fn from_char(c: char) -> Option<SnakeDirection> {
+        match c {
+            'U' => Some(SnakeDirection::Up),
+            'D' => Some(SnakeDirection::Down),
+            'L' => Some(SnakeDirection::Left),
+            'R' => Some(SnakeDirection::Right),
+            _ => None,
+        }
+    }
+
+    /// The direction of a single step between two neighbouring cells.
+    pub fn from_delta(delta: IVec2) -> Option<SnakeDirection> {
+        match (delta.x, delta.y) {
+            (-1, 0) => Some(SnakeDirection::Left),
+            (1, 0) => Some(SnakeDirection::Right),
+            (0, 1) => Some(SnakeDirection::Up),
+            (0, -1) => Some(SnakeDirection::Down),
+            _ => None,
+        }
+    }
+}
+
 // stuff you want to export to other mods
 #[derive(Component)]
 pub struct MainCamera;
@@ -26,10 +80,40 @@ impl ControlScheme {
     }
 }
 
+/// How many turns a player can buffer ahead of the snake.
+pub const DIRECTION_QUEUE_SIZE: usize = 3;
+
+/**
+ * Turns pressed by a player that the snake has not taken yet, consumed one per tick by `move_snakes`.
+ */
+#[derive(Component, Default)]
+pub struct DirectionQueue(pub VecDeque<SnakeDirection>);
+
+impl DirectionQueue {
+    /// Queues a turn if it changes the direction the snake will be heading in by then
+    /// without reversing it. `moving` is the direction the snake last moved in.
+    pub fn push(&mut self, direction: SnakeDirection, moving: &SnakeDirection) -> bool {
+        let heading = self.0.back().unwrap_or(moving);
+        if self.0.len() >= DIRECTION_QUEUE_SIZE
+            || *heading == direction
+            || heading.opposite() == direction
+        {
+            return false;
+        }
+
+        self.0.push_back(direction);
+        true
+    }
+
+    pub fn pop(&mut self) -> Option<SnakeDirection> {
+        self.0.pop_front()
+    }
+}
+
 #[derive(Component)]
 pub struct SnakeHeadRef(pub Option<Entity>);
 
-#[derive(Component)]
+#[derive(Component, Clone, Copy)]
 pub struct SnakeTextureIndex(pub usize);
 
 #[derive(Component)]
@@ -51,7 +135,46 @@ pub struct PlayerBundle {
     pub player: Player,
     pub name: Named,
     pub control_scheme: ControlScheme,
+    pub direction_queue: DirectionQueue,
     pub snake_head_ref: SnakeHeadRef,
     pub score: Score,
     pub texture_index: SnakeTextureIndex,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_rejects_reversing_the_direction_moved_in() {
+        let mut queue = DirectionQueue::default();
+        assert!(!queue.push(SnakeDirection::Left, &SnakeDirection::Right));
+        assert!(queue.0.is_empty());
+    }
+
+    #[test]
+    fn push_checks_against_the_last_queued_turn() {
+        let mut queue = DirectionQueue::default();
+        assert!(queue.push(SnakeDirection::Up, &SnakeDirection::Right));
+        // reversing the queued turn, even though it is a turn for the snake as it moves now
+        assert!(!queue.push(SnakeDirection::Down, &SnakeDirection::Right));
+        assert!(queue.push(SnakeDirection::Left, &SnakeDirection::Right));
+        assert_eq!(
+            queue.0,
+            VecDeque::from([SnakeDirection::Up, SnakeDirection::Left])
+        );
+    }
+
+    #[test]
+    fn push_rejects_repeats_and_a_full_queue() {
+        let mut queue = DirectionQueue::default();
+        assert!(!queue.push(SnakeDirection::Right, &SnakeDirection::Right));
+        assert!(queue.push(SnakeDirection::Up, &SnakeDirection::Right));
+        assert!(!queue.push(SnakeDirection::Up, &SnakeDirection::Right));
+        assert!(queue.push(SnakeDirection::Left, &SnakeDirection::Right));
+        assert!(queue.push(SnakeDirection::Down, &SnakeDirection::Right));
+        assert_eq!(queue.0.len(), DIRECTION_QUEUE_SIZE);
+        assert!(!queue.push(SnakeDirection::Right, &SnakeDirection::Right));
+        assert_eq!(queue.pop(), Some(SnakeDirection::Up));
+    }
+}
@@ -0,0 +1,91 @@
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::grid::ArenaMode;
+use super::rules::GameRules;
+
+/// The port the dedicated server listens on unless told otherwise.
+pub const DEFAULT_SERVER_PORT: u16 = 7777;
+/// Snapshots of a full board are the largest packets, well below this.
+pub const MAX_PACKET_SIZE: usize = 65_507;
+
+/// Messages from a client to the dedicated server.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum ClientMessage {
+    /// Asks for a seat, repeated until answered. Also asks for the current match to be resent.
+    Join {
+        name: String,
+    },
+    /// A turn as `U`, `D`, `L` or `R`.
+    Turn {
+        direction: char,
+    },
+    KeepAlive,
+    Leave,
+}
+
+/// Messages from the dedicated server to its clients.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum ServerMessage {
+    Welcome {
+        slot: usize,
+    },
+    Full,
+    MatchStart {
+        match_id: u32,
+        level: usize,
+        arena_mode: ArenaMode,
+        rules: GameRules,
+    },
+    Snapshot(Snapshot),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SnakeState {
+    pub slot: usize,
+    pub name: String,
+    pub skin: usize,
+    /// Head first.
+    pub cells: Vec<[i32; 2]>,
+    pub direction: char,
+    pub dead: bool,
+    pub score: u32,
+}
+
+/// The whole board after a tick.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Snapshot {
+    pub match_id: u32,
+    pub tick: u32,
+    /// Seconds until the next tick, for clients to interpolate over.
+    pub tick_length: f32,
+    pub snakes: Vec<SnakeState>,
+    pub apples: Vec<[i32; 2]>,
+    pub over: bool,
+}
+
+pub fn send<T: Serialize>(socket: &UdpSocket, to: SocketAddr, message: &T) -> io::Result<()> {
+    let bytes = serde_json::to_vec(message)?;
+    socket.send_to(&bytes, to)?;
+    Ok(())
+}
+
+/// The next message waiting on a non-blocking socket. Malformed packets are skipped.
+pub fn receive<T: DeserializeOwned>(socket: &UdpSocket) -> Option<(T, SocketAddr)> {
+    let mut buf = vec![0; MAX_PACKET_SIZE];
+    loop {
+        match socket.recv_from(&mut buf) {
+            Ok((len, from)) => {
+                if let Ok(message) = serde_json::from_slice(&buf[..len]) {
+                    return Some((message, from));
+                }
+            }
+            // an unreachable peer can show up as a receive error, there is nothing else to read
+            Err(_) => return None,
+        }
+    }
+}
@@ -0,0 +1,447 @@
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+};
+
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::GameState;
+
+use super::components::{Apple, Dead, SnakeBodyPart, SnakeHead, Tail};
+use super::grid::{Grid, GridPos};
+use super::level::{spawn_tilemap, ActiveLevel, Level, LevelAssets, SelectedLevel};
+use super::prelude::{BodyRef, SnakeDirection, SnakeTextureIndex};
+use super::protocol::{self, ClientMessage, ServerMessage, Snapshot};
+use super::rules::GameRules;
+use super::sprites::attach_sprites;
+use super::systems::orient_snakes;
+
+/// Seconds between repeated join requests while the server has not answered.
+const JOIN_INTERVAL: f32 = 0.5;
+const KEEP_ALIVE_INTERVAL: f32 = 1.;
+
+const TURN_KEYS: [(KeyCode, SnakeDirection); 8] = [
+    (KeyCode::ArrowUp, SnakeDirection::Up),
+    (KeyCode::ArrowLeft, SnakeDirection::Left),
+    (KeyCode::ArrowDown, SnakeDirection::Down),
+    (KeyCode::ArrowRight, SnakeDirection::Right),
+    (KeyCode::KeyW, SnakeDirection::Up),
+    (KeyCode::KeyA, SnakeDirection::Left),
+    (KeyCode::KeyS, SnakeDirection::Down),
+    (KeyCode::KeyD, SnakeDirection::Right),
+];
+
+/**
+ * A connection to a dedicated server. The server runs the rules, this client only sends turns
+ * and draws the snapshots it gets back.
+ */
+#[derive(Resource)]
+pub struct RemoteSession {
+    socket: UdpSocket,
+    server: SocketAddr,
+    name: String,
+    slot: Option<usize>,
+    full: bool,
+    match_id: u32,
+    /// The tick of the latest snapshot drawn this match. Older ones arriving late are dropped.
+    last_tick: Option<u32>,
+    /// Whether a snapshot of a match we have not been told about has arrived.
+    missed_start: bool,
+    since_sent: f32,
+    /// Seconds since the latest snapshot and until the next one is due.
+    snapshot_age: f32,
+    tick_length: f32,
+}
+
+impl RemoteSession {
+    pub fn connect(server: SocketAddr, name: String) -> io::Result<Self> {
+        let socket = UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], 0)))?;
+        socket.set_nonblocking(true)?;
+        Ok(RemoteSession {
+            socket,
+            server,
+            name,
+            slot: None,
+            full: false,
+            match_id: 0,
+            last_tick: None,
+            missed_start: false,
+            since_sent: JOIN_INTERVAL,
+            snapshot_age: 0.,
+            tick_length: 1.,
+        })
+    }
+
+    fn send(&self, message: &ClientMessage) {
+        if let Err(err) = protocol::send(&self.socket, self.server, message) {
+            warn!("could not reach the server: {err}");
+        }
+    }
+}
+
+impl Drop for RemoteSession {
+    fn drop(&mut self) {
+        self.send(&ClientMessage::Leave);
+    }
+}
+
+/// Everything drawn for the current match, despawned when the next one starts.
+#[derive(Component)]
+struct RemoteEntity;
+
+/// The cell an entity was in before the latest snapshot, to slide it from.
+#[derive(Component)]
+struct PreviousPos(IVec2);
+
+#[derive(Component)]
+struct RemoteStatusText;
+
+/// The entities drawing each snake, by slot.
+#[derive(Resource, Default)]
+struct RemoteSnakes(HashMap<usize, (Entity, Vec<Entity>)>);
+
+#[derive(Resource, Default)]
+struct RemoteApples(Vec<Entity>);
+
+#[derive(Resource, Default)]
+struct Scoreboard(String);
+
+fn cell(pos: [i32; 2]) -> IVec2 {
+    IVec2::from_array(pos)
+}
+
+fn spawn_status_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(8.),
+                top: Val::Px(8.),
+                ..default()
+            },
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font_size: 30.,
+                    color: Color::WHITE,
+                    font: asset_server.load("fonts/visitor.ttf"),
+                },
+            ),
+            ..default()
+        },
+        RemoteStatusText,
+        StateScoped(GameState::RemoteGame),
+    ));
+}
+
+/**
+ * Joins the server and keeps the seat, asking again for the start of the match when
+ * snapshots of an unknown match come in.
+ */
+fn keep_connected(time: Res<Time<Real>>, mut session: ResMut<RemoteSession>) {
+    session.since_sent += time.delta_seconds();
+    let joining = session.slot.is_none() || session.missed_start;
+    let interval = if joining {
+        JOIN_INTERVAL
+    } else {
+        KEEP_ALIVE_INTERVAL
+    };
+    if session.since_sent < interval {
+        return;
+    }
+    session.since_sent = 0.;
+    if joining {
+        session.send(&ClientMessage::Join {
+            name: session.name.clone(),
+        });
+    } else {
+        session.send(&ClientMessage::KeepAlive);
+    }
+}
+
+fn send_turns(keyboard: Res<ButtonInput<KeyCode>>, session: Res<RemoteSession>) {
+    for (key, direction) in TURN_KEYS.iter() {
+        if keyboard.just_pressed(*key) {
+            session.send(&ClientMessage::Turn {
+                direction: direction.to_char(),
+            });
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn receive_server_messages(
+    mut commands: Commands,
+    time: Res<Time<Real>>,
+    mut session: ResMut<RemoteSession>,
+    asset_server: Res<AssetServer>,
+    level_assets: Res<LevelAssets>,
+    levels: Res<Assets<Level>>,
+    mut grid: ResMut<Grid>,
+    mut rules: ResMut<GameRules>,
+    mut active_level: ResMut<ActiveLevel>,
+    mut snakes: ResMut<RemoteSnakes>,
+    mut apples: ResMut<RemoteApples>,
+    mut scoreboard: ResMut<Scoreboard>,
+    drawn: Query<Entity, With<RemoteEntity>>,
+    mut positions: Query<&mut GridPos>,
+    mut heads: Query<&mut SnakeHead>,
+) {
+    session.snapshot_age += time.delta_seconds();
+
+    while let Some((message, _)) = protocol::receive::<ServerMessage>(&session.socket) {
+        match message {
+            ServerMessage::Welcome { slot } => {
+                session.slot = Some(slot);
+                session.full = false;
+            }
+            ServerMessage::Full => {
+                session.full = true;
+            }
+            ServerMessage::MatchStart {
+                match_id,
+                level,
+                arena_mode,
+                rules: match_rules,
+            } if match_id != session.match_id => {
+                session.match_id = match_id;
+                session.last_tick = None;
+                session.missed_start = false;
+                for entity in drawn.iter() {
+                    commands.entity(entity).despawn_recursive();
+                }
+                snakes.0.clear();
+                apples.0.clear();
+
+                *rules = match_rules;
+                let level = SelectedLevel(level).level(Some(&level_assets), &levels, &rules);
+                grid.width = level.width;
+                grid.height = level.height;
+                grid.tile_size = rules.tile_size;
+                grid.mode = arena_mode;
+                spawn_tilemap(
+                    &mut commands,
+                    asset_server.load("textures/tiles.png"),
+                    &grid,
+                    &level,
+                    || (RemoteEntity, StateScoped(GameState::RemoteGame)),
+                );
+                active_level.0 = level;
+            }
+            ServerMessage::Snapshot(snapshot) => {
+                if snapshot.match_id != session.match_id {
+                    session.missed_start = true;
+                    continue;
+                }
+                // UDP can repeat or reorder packets, which would roll the board back
+                if session.last_tick.is_some_and(|last| snapshot.tick <= last) {
+                    continue;
+                }
+                session.last_tick = Some(snapshot.tick);
+                session.snapshot_age = 0.;
+                session.tick_length = snapshot.tick_length.max(f32::EPSILON);
+                scoreboard.0 = snapshot
+                    .snakes
+                    .iter()
+                    .map(|snake| format!("{} {}", snake.name.to_uppercase(), snake.score))
+                    .collect::<Vec<_>>()
+                    .join("   ");
+                apply_snapshot(
+                    &mut commands,
+                    &snapshot,
+                    &mut snakes,
+                    &mut apples,
+                    &mut positions,
+                    &mut heads,
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Moves `entity` to `pos`, remembering where it came from, or spawns it there.
+fn place(
+    commands: &mut Commands,
+    positions: &mut Query<&mut GridPos>,
+    entity: Option<Entity>,
+    pos: IVec2,
+    bundle: impl Bundle,
+) -> Entity {
+    if let Some(entity) = entity {
+        match positions.get_mut(entity) {
+            Ok(mut grid_pos) => {
+                commands.entity(entity).insert(PreviousPos(grid_pos.0));
+                grid_pos.0 = pos;
+            }
+            // spawned for an earlier snapshot this frame and not in the world yet
+            Err(_) => {
+                commands
+                    .entity(entity)
+                    .insert((GridPos(pos), PreviousPos(pos)));
+            }
+        }
+        return entity;
+    }
+    commands
+        .spawn((
+            GridPos(pos),
+            PreviousPos(pos),
+            bundle,
+            RemoteEntity,
+            StateScoped(GameState::RemoteGame),
+        ))
+        .id()
+}
+
+/**
+ * Brings the drawn snakes and apples in line with a snapshot, spawning and despawning
+ * segments as the snakes grow and shrink.
+ */
+fn apply_snapshot(
+    commands: &mut Commands,
+    snapshot: &Snapshot,
+    snakes: &mut RemoteSnakes,
+    apples: &mut RemoteApples,
+    positions: &mut Query<&mut GridPos>,
+    heads: &mut Query<&mut SnakeHead>,
+) {
+    for snake in snapshot.snakes.iter() {
+        let Some((&head_cell, body_cells)) = snake.cells.split_first() else {
+            continue;
+        };
+        let direction = SnakeDirection::from_char(snake.direction).unwrap_or(SnakeDirection::Right);
+        let skin = SnakeTextureIndex(snake.skin);
+        let (head, mut body) = match snakes.0.remove(&snake.slot) {
+            Some((head, body)) => (Some(head), body),
+            None => (None, Vec::new()),
+        };
+
+        let head = place(
+            commands,
+            positions,
+            head,
+            cell(head_cell),
+            (
+                SnakeHead {
+                    direction: direction.clone(),
+                },
+                skin,
+            ),
+        );
+        if let Ok(mut snake_head) = heads.get_mut(head) {
+            snake_head.direction = direction;
+        }
+
+        for extra in body.drain(body_cells.len().min(body.len())..) {
+            commands.entity(extra).despawn_recursive();
+        }
+        for (i, &body_cell) in body_cells.iter().enumerate() {
+            let part = place(
+                commands,
+                positions,
+                body.get(i).copied(),
+                cell(body_cell),
+                (SnakeBodyPart, skin),
+            );
+            if i == body.len() {
+                body.push(part);
+            }
+            commands.entity(part).remove::<Tail>();
+        }
+        if let Some(&tail) = body.last() {
+            commands.entity(tail).insert(Tail);
+        }
+
+        commands.entity(head).insert(BodyRef(body.clone()));
+        if snake.dead {
+            commands.entity(head).insert(Dead);
+        }
+        snakes.0.insert(snake.slot, (head, body));
+    }
+
+    for extra in apples.0.drain(snapshot.apples.len().min(apples.0.len())..) {
+        commands.entity(extra).despawn_recursive();
+    }
+    for (i, &apple_cell) in snapshot.apples.iter().enumerate() {
+        let apple = place(
+            commands,
+            positions,
+            apples.0.get(i).copied(),
+            cell(apple_cell),
+            Apple,
+        );
+        if i == apples.0.len() {
+            apples.0.push(apple);
+        }
+    }
+}
+
+/**
+ * Slides everything from its previous cell to its current one over the length of a tick,
+ * so the board moves smoothly between snapshots. Jumps through portals or across the edge
+ * of a wrap-around arena are not slid.
+ */
+fn interpolate_transforms(
+    grid: Res<Grid>,
+    session: Res<RemoteSession>,
+    mut query: Query<(&GridPos, &PreviousPos, &mut Transform)>,
+) {
+    let t = (session.snapshot_age / session.tick_length).min(1.);
+    for (pos, previous, mut transform) in query.iter_mut() {
+        let offset = grid.offset(previous.0, pos.0);
+        let target = grid.to_world(pos.0);
+        let translation = if offset.abs().element_sum() <= 1 {
+            target - offset.as_vec2() * grid.tile_size * (1. - t)
+        } else {
+            target
+        };
+        transform.translation = translation.extend(transform.translation.z);
+    }
+}
+
+fn update_status_text(
+    session: Res<RemoteSession>,
+    scoreboard: Res<Scoreboard>,
+    mut texts: Query<&mut Text, With<RemoteStatusText>>,
+) {
+    let status = if session.full {
+        "SERVER FULL".to_string()
+    } else if session.slot.is_none() {
+        format!("CONNECTING TO {}", session.server)
+    } else if session.match_id == 0 {
+        "WAITING FOR THE MATCH TO START".to_string()
+    } else {
+        scoreboard.0.clone()
+    };
+    for mut text in texts.iter_mut() {
+        text.sections[0].value.clone_from(&status);
+    }
+}
+
+/**
+ * Plays on a dedicated server, started with `--connect ADDRESS`. Needs a `RemoteSession`.
+ */
+pub struct RemotePlugin;
+impl Plugin for RemotePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RemoteSnakes>()
+            .init_resource::<RemoteApples>()
+            .init_resource::<Scoreboard>()
+            .add_systems(OnEnter(GameState::RemoteGame), spawn_status_text)
+            .add_systems(
+                Update,
+                (
+                    keep_connected,
+                    send_turns,
+                    receive_server_messages,
+                    attach_sprites,
+                    interpolate_transforms,
+                    orient_snakes,
+                    update_status_text,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::RemoteGame)),
+            );
+    }
+}
@@ -0,0 +1,494 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Seed, GamePhase, GameState};
+
+use super::components::{MatchSetup, TickSet};
+use super::grid::{ArenaMode, Grid};
+use super::level::SelectedLevel;
+use super::prelude::{
+    ControlScheme, DirectionQueue, Named, Player, PlayerBundle, Score, SnakeDirection,
+    SnakeHeadRef, SnakeTextureIndex,
+};
+use super::rules::{GameRules, RulesAssets};
+use super::systems::move_snakes;
+
+const REPLAY_DIR: &str = "replays";
+const MIN_PLAYBACK_SPEED: f32 = 0.25;
+const MAX_PLAYBACK_SPEED: f32 = 8.;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReplayPlayer {
+    pub name: String,
+    pub skin: usize,
+}
+
+/**
+ * Everything needed to play a match again exactly: the seed, arena and rules it started with and
+ * the turn every player took each tick. `inputs` holds one character per player per tick,
+ * `U`, `D`, `L` or `R` for a turn and `.` for going straight on.
+ */
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Replay {
+    pub seed: u64,
+    pub level: usize,
+    pub arena_mode: ArenaMode,
+    pub rules: GameRules,
+    pub players: Vec<ReplayPlayer>,
+    pub inputs: String,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> io::Result<Replay> {
+        let text = fs::read_to_string(path)?;
+        let replay: Replay = serde_json::from_str(&text)?;
+        replay.validate()?;
+        Ok(replay)
+    }
+
+    /// Refuses inputs that would otherwise play back as going straight on.
+    fn validate(&self) -> io::Result<()> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        if self.players.is_empty() {
+            return Err(invalid("replay has no players".to_string()));
+        }
+        if let Some(c) = self
+            .inputs
+            .chars()
+            .find(|&c| c != '.' && SnakeDirection::from_char(c).is_none())
+        {
+            return Err(invalid(format!("unknown input '{c}' in replay")));
+        }
+        if !self.inputs.len().is_multiple_of(self.players.len()) {
+            return Err(invalid("replay ends partway through a tick".to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn save(&self) -> io::Result<PathBuf> {
+        self.save_in(Path::new(REPLAY_DIR))
+    }
+
+    fn save_in(&self, dir: &Path) -> io::Result<PathBuf> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let path = dir.join(format!("{timestamp}-{}.replay.json", self.seed));
+        fs::create_dir_all(dir)?;
+        fs::write(&path, serde_json::to_string(self)?)?;
+        Ok(path)
+    }
+
+    /// The saved replays, newest first.
+    pub fn list() -> Vec<PathBuf> {
+        let Ok(entries) = fs::read_dir(REPLAY_DIR) else {
+            return Vec::new();
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.to_string_lossy().ends_with(".replay.json"))
+            .collect();
+        // file names start with the time they were saved
+        paths.sort();
+        paths.reverse();
+        paths
+    }
+
+    pub fn ticks(&self) -> usize {
+        self.inputs.len() / self.players.len().max(1)
+    }
+
+    /// The turns taken at `tick`, in player order.
+    fn tick_inputs(&self, tick: usize) -> impl Iterator<Item = Option<SnakeDirection>> + '_ {
+        let n = self.players.len();
+        self.inputs
+            .get(tick * n..(tick + 1) * n)
+            .unwrap_or_default()
+            .chars()
+            .map(SnakeDirection::from_char)
+    }
+}
+
+/// The replay of the match being played, saved once every snake is dead.
+#[derive(Resource)]
+struct Recording(Replay);
+
+/**
+ * Present when watching a replay, picked from the main menu or with `--replay`. Matches are then driven from the replay
+ * instead of the players' controls.
+ */
+#[derive(Resource)]
+pub struct Playback {
+    pub replay: Replay,
+    pub tick: usize,
+    pub speed: f32,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        Playback {
+            replay,
+            tick: 0,
+            speed: 1.,
+        }
+    }
+}
+
+/// Sets up the arena, rules and seed of `replay` and plays it back from the next match on.
+pub fn start_playback(world: &mut World, replay: Replay) {
+    world.insert_resource(Seed(replay.seed));
+    world.insert_resource(SelectedLevel(replay.level));
+    world.insert_resource(Grid {
+        mode: replay.arena_mode,
+        ..default()
+    });
+    world.insert_resource(replay.rules.clone());
+    world.insert_resource(Playback::new(replay));
+}
+
+/// Goes back to playing matches, by the rules on disk rather than those of the replay.
+fn stop_playback(
+    mut commands: Commands,
+    rules_assets: Option<Res<RulesAssets>>,
+    assets: Res<Assets<GameRules>>,
+    mut rules: ResMut<GameRules>,
+) {
+    commands.remove_resource::<Playback>();
+    if let Some(loaded) = rules_assets.and_then(|rules_assets| assets.get(&rules_assets.rules)) {
+        *rules = loaded.clone();
+    }
+}
+
+#[derive(Component)]
+struct PlaybackText;
+
+fn start_recording(
+    mut commands: Commands,
+    seed: Res<Seed>,
+    selected_level: Res<SelectedLevel>,
+    grid: Res<Grid>,
+    rules: Res<GameRules>,
+    players: Query<(&Named, &SnakeTextureIndex), With<Player>>,
+) {
+    commands.insert_resource(Recording(Replay {
+        seed: seed.0,
+        level: selected_level.0,
+        arena_mode: grid.mode,
+        rules: rules.clone(),
+        players: players
+            .iter()
+            .map(|(name, skin)| ReplayPlayer {
+                name: name.0.clone(),
+                skin: skin.0,
+            })
+            .collect(),
+        inputs: String::new(),
+    }));
+}
+
+/**
+ * Writes down the turn each player is about to take this tick. Runs right before `move_snakes`
+ * takes it from the direction queue.
+ */
+fn record_inputs(mut recording: ResMut<Recording>, players: Query<&DirectionQueue, With<Player>>) {
+    for queue in players.iter() {
+        recording
+            .0
+            .inputs
+            .push(queue.0.front().map_or('.', SnakeDirection::to_char));
+    }
+}
+
+fn save_recording(recording: Res<Recording>) {
+    match recording.0.save() {
+        Ok(path) => info!("replay saved to {}", path.display()),
+        Err(err) => warn!("could not save replay: {err}"),
+    }
+}
+
+fn stop_recording(mut commands: Commands) {
+    commands.remove_resource::<Recording>();
+}
+
+fn spawn_replay_players(mut commands: Commands, playback: Res<Playback>) {
+    for player in playback.replay.players.iter() {
+        commands.spawn(PlayerBundle {
+            player: Player,
+            name: Named(player.name.clone()),
+            control_scheme: ControlScheme {
+                directional_controls: HashMap::new(),
+            },
+            direction_queue: DirectionQueue::default(),
+            snake_head_ref: SnakeHeadRef(None),
+            score: Score { value: 0 },
+            texture_index: SnakeTextureIndex(player.skin),
+        });
+    }
+}
+
+fn spawn_playback_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(8.),
+                bottom: Val::Px(8.),
+                ..default()
+            },
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font_size: 20.,
+                    color: Color::WHITE,
+                    font: asset_server.load("fonts/visitor.ttf"),
+                },
+            ),
+            ..default()
+        },
+        PlaybackText,
+        StateScoped(GameState::InGame),
+    ));
+}
+
+/**
+ * Puts the recorded turns of this tick in the players' direction queues for `move_snakes`.
+ */
+fn feed_inputs(
+    mut playback: ResMut<Playback>,
+    mut players: Query<&mut DirectionQueue, With<Player>>,
+) {
+    let playback = &mut *playback;
+    for (mut queue, direction) in players
+        .iter_mut()
+        .zip(playback.replay.tick_inputs(playback.tick))
+    {
+        queue.0.clear();
+        queue.0.extend(direction);
+    }
+    playback.tick += 1;
+}
+
+/**
+ * Space pauses, `.` steps a single tick while paused, `-` and `=` halve and double the speed
+ * and R rewinds to the start of the match.
+ */
+fn playback_controls(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut playback: ResMut<Playback>,
+    mut time: ResMut<Time<Virtual>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keyboard.just_pressed(KeyCode::Space) {
+        if time.is_paused() {
+            time.unpause();
+        } else {
+            time.pause();
+        }
+    }
+    if keyboard.just_pressed(KeyCode::Minus) {
+        playback.speed = (playback.speed / 2.).max(MIN_PLAYBACK_SPEED);
+    }
+    if keyboard.just_pressed(KeyCode::Equal) {
+        playback.speed = (playback.speed * 2.).min(MAX_PLAYBACK_SPEED);
+    }
+    time.set_relative_speed(playback.speed);
+
+    if keyboard.just_pressed(KeyCode::KeyR) {
+        next_state.set(GameState::LeaveGame);
+    }
+}
+
+fn step_playback(world: &mut World) {
+    let step = world
+        .resource::<ButtonInput<KeyCode>>()
+        .just_pressed(KeyCode::Period);
+    if step && world.resource::<Time<Virtual>>().is_paused() {
+        world.run_schedule(FixedUpdate);
+    }
+}
+
+fn restart_playback(mut playback: ResMut<Playback>, mut next_state: ResMut<NextState<GameState>>) {
+    playback.tick = 0;
+    next_state.set(GameState::InGame);
+}
+
+fn update_playback_text(
+    playback: Res<Playback>,
+    time: Res<Time<Virtual>>,
+    mut texts: Query<&mut Text, With<PlaybackText>>,
+) {
+    for mut text in texts.iter_mut() {
+        text.sections[0].value = format!(
+            "replay {}/{} x{}{}",
+            playback.tick,
+            playback.replay.ticks(),
+            playback.speed,
+            if time.is_paused() { " paused" } else { "" }
+        );
+    }
+}
+
+/**
+ * Records every match to `replays/` and, when started with `--replay`, plays one back instead.
+ */
+pub struct ReplayPlugin;
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        let playback = resource_exists::<Playback>;
+
+        app.add_systems(
+            OnEnter(GameState::InGame),
+            (
+                start_recording.after(MatchSetup).run_if(not(playback)),
+                (spawn_replay_players.before(MatchSetup), spawn_playback_text).run_if(playback),
+            ),
+        )
+        .add_systems(
+            FixedUpdate,
+            (
+                feed_inputs.in_set(TickSet::Input).run_if(playback),
+                record_inputs
+                    .in_set(TickSet::Simulation)
+                    .before(move_snakes)
+                    .run_if(resource_exists::<Recording>),
+            )
+                .run_if(in_state(GamePhase::Playing)),
+        )
+        // every round of a versus match is a replay of its own
+        .add_systems(
+            OnEnter(GamePhase::Dead),
+            save_recording.run_if(resource_exists::<Recording>),
+        )
+        .add_systems(
+            OnEnter(GamePhase::RoundOver),
+            save_recording.run_if(resource_exists::<Recording>),
+        )
+        .add_systems(OnExit(GameState::InGame), stop_recording)
+        .add_systems(
+            OnEnter(GameState::LeaveGame),
+            restart_playback.run_if(playback),
+        )
+        .add_systems(OnEnter(GameState::MainMenu), stop_playback.run_if(playback))
+        .add_systems(
+            Update,
+            (playback_controls, step_playback, update_playback_text)
+                .chain()
+                .run_if(playback.and_then(in_state(GameState::InGame))),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use std::collections::VecDeque;
+
+    fn replay(players: usize, inputs: &str) -> Replay {
+        Replay {
+            seed: 7,
+            level: 0,
+            arena_mode: ArenaMode::default(),
+            rules: GameRules::default(),
+            players: (0..players)
+                .map(|i| ReplayPlayer {
+                    name: format!("P{i}"),
+                    skin: i,
+                })
+                .collect(),
+            inputs: inputs.to_string(),
+        }
+    }
+
+    /// A directory of its own for each test, as they run in parallel.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("snek-replay-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn write_file(dir: &Path, text: &str) -> PathBuf {
+        fs::create_dir_all(dir).unwrap();
+        let path = dir.join("broken.replay.json");
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn recorded_inputs_play_back_after_saving_and_loading() {
+        use SnakeDirection::*;
+        let turns = [
+            [Some(Right), None],
+            [None, None],
+            [Some(Up), Some(Left)],
+            [None, Some(Down)],
+        ];
+
+        let mut world = World::new();
+        world.insert_resource(Recording(replay(2, "")));
+        let players: Vec<Entity> = (0..2)
+            .map(|_| world.spawn((Player, DirectionQueue::default())).id())
+            .collect();
+        for tick in turns.iter() {
+            for (&player, turn) in players.iter().zip(tick) {
+                world.get_mut::<DirectionQueue>(player).unwrap().0 =
+                    turn.iter().cloned().collect::<VecDeque<_>>();
+            }
+            world.run_system_once(record_inputs);
+        }
+
+        let dir = scratch_dir("round-trip");
+        let path = world.resource::<Recording>().0.save_in(&dir).unwrap();
+        let loaded = Replay::load(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded.ticks(), turns.len());
+        for (tick, expected) in turns.iter().enumerate() {
+            assert_eq!(loaded.tick_inputs(tick).collect::<Vec<_>>(), expected);
+        }
+    }
+
+    #[test]
+    fn truncated_file_is_rejected() {
+        let text = serde_json::to_string(&replay(2, "R.UL")).unwrap();
+        let dir = scratch_dir("truncated-file");
+        let path = write_file(&dir, &text[..text.len() / 2]);
+        let result = Replay::load(&path);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn inputs_cut_off_partway_through_a_tick_are_rejected() {
+        let text = serde_json::to_string(&replay(2, "R.U")).unwrap();
+        let dir = scratch_dir("partial-tick");
+        let path = write_file(&dir, &text);
+        let result = Replay::load(&path);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn unknown_inputs_are_rejected() {
+        let text = serde_json::to_string(&replay(2, "R.UX")).unwrap();
+        let dir = scratch_dir("unknown-input");
+        let path = write_file(&dir, &text);
+        let result = Replay::load(&path);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn replay_without_players_is_rejected() {
+        assert!(replay(0, "").validate().is_err());
+    }
+}
@@ -0,0 +1,226 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{
+    menu::{
+        highlight_menu_entries, spawn_menu_entries, MenuAction, MenuCursor, MenuEntry, MenuInput,
+        MENU_SELECTED_COLOR,
+    },
+    netplay::NetSession,
+    GameState,
+};
+
+use super::prelude::{Named, Player, Score, SnakeHeadRef};
+use super::replay::Playback;
+use super::rounds::VersusMatch;
+use super::spawner::BoardFull;
+use super::stats::{match_winner, DeathCause, PlayerStats};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResultsEntry {
+    /// Plays again with the same players and settings, or watches a replay again.
+    Rematch,
+    Lobby,
+    MainMenu,
+}
+
+#[derive(Resource)]
+pub struct ResultsMenu {
+    entries: Vec<ResultsEntry>,
+    cursor: MenuCursor,
+}
+
+fn survived_label(survived: Duration) -> String {
+    format!("{:.1}S", survived.as_secs_f32())
+}
+
+fn cause_label(cause: Option<DeathCause>, name_of: impl Fn(Entity) -> String) -> String {
+    match cause {
+        None => "SURVIVED".to_string(),
+        Some(DeathCause::Wall) => "HIT A WALL".to_string(),
+        Some(DeathCause::SelfBite) => "BIT ITSELF".to_string(),
+        Some(DeathCause::RanInto(other)) => format!("RAN INTO {}", name_of(other)),
+        Some(DeathCause::BittenBy(other)) => format!("BITTEN BY {}", name_of(other)),
+        Some(DeathCause::HeadOn(other)) => format!("HEAD ON WITH {}", name_of(other)),
+    }
+}
+
+/**
+ * Shows how every player did once the match is over, and who won when there was more than one.
+ * A versus match is won on rounds, which get a column of their own.
+ */
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_results(
+    mut commands: Commands,
+    board_full: Res<BoardFull>,
+    versus: Option<Res<VersusMatch>>,
+    asset_server: Res<AssetServer>,
+    net_session: Option<Res<NetSession>>,
+    playback: Option<Res<Playback>>,
+    players: Query<(Entity, &Named, &Score, &SnakeHeadRef, &PlayerStats), With<Player>>,
+) {
+    let font = asset_server.load("fonts/visitor.ttf");
+    let text_style = |font_size: f32, color: Color| TextStyle {
+        font_size,
+        color,
+        font: font.clone(),
+    };
+
+    let mut players: Vec<_> = players.iter().collect();
+    players.sort_by_key(|(player, ..)| *player);
+    let winner = match versus.as_ref() {
+        Some(versus) => versus.winner(),
+        None => match_winner(
+            players
+                .iter()
+                .map(|(player, _, score, _, stats)| (*player, *stats, score.value)),
+        ),
+    };
+    let name_of = |head: Entity| {
+        players
+            .iter()
+            .find(|(_, _, _, head_ref, _)| head_ref.0 == Some(head))
+            .map_or("A SNAKE".to_string(), |(_, name, ..)| name.0.to_uppercase())
+    };
+    let title = match (winner, board_full.0) {
+        (_, true) => "BOARD CLEARED!".to_string(),
+        (Some(winner), false) => players
+            .iter()
+            .find(|(player, ..)| *player == winner)
+            .map_or(String::new(), |(_, name, ..)| {
+                format!("{} WINS!", name.0.to_uppercase())
+            }),
+        (None, false) if players.len() > 1 => "DRAW!".to_string(),
+        (None, false) => "GAME OVER".to_string(),
+    };
+
+    // an online match is only started again by the host, from the lobby
+    let entries: Vec<ResultsEntry> = match (playback.is_some(), net_session.is_some()) {
+        (true, _) => vec![ResultsEntry::Rematch, ResultsEntry::MainMenu],
+        (false, true) => vec![ResultsEntry::Lobby],
+        (false, false) => vec![ResultsEntry::Rematch, ResultsEntry::Lobby],
+    };
+    let labels: Vec<&str> = entries
+        .iter()
+        .map(|entry| match (entry, playback.is_some()) {
+            (ResultsEntry::Rematch, true) => "WATCH AGAIN",
+            (ResultsEntry::Rematch, false) => "REMATCH",
+            (ResultsEntry::Lobby, _) => "LOBBY",
+            (ResultsEntry::MainMenu, _) => "MAIN MENU",
+        })
+        .collect();
+
+    let mut headers = vec![
+        "PLAYER", "SCORE", "LENGTH", "APPLES", "KILLS", "SURVIVED", "",
+    ];
+    let mut columns = vec![
+        GridTrack::px(200.),
+        GridTrack::px(100.),
+        GridTrack::px(110.),
+        GridTrack::px(100.),
+        GridTrack::px(90.),
+        GridTrack::px(120.),
+        GridTrack::px(300.),
+    ];
+    if versus.is_some() {
+        headers.insert(1, "ROUNDS");
+        columns.insert(1, GridTrack::px(110.));
+    }
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(16.),
+                    ..default()
+                },
+                background_color: Color::BLACK.with_alpha(0.7).into(),
+                z_index: ZIndex::Global(4),
+                ..default()
+            },
+            StateScoped(GameState::InGame),
+        ))
+        .with_children(|builder| {
+            builder.spawn(TextBundle::from_section(
+                title,
+                text_style(60., Color::WHITE),
+            ));
+            builder
+                .spawn(NodeBundle {
+                    style: Style {
+                        display: Display::Grid,
+                        grid_template_columns: columns,
+                        row_gap: Val::Px(6.),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|grid| {
+                    for header in headers {
+                        grid.spawn(TextBundle::from_section(
+                            header,
+                            text_style(24., MENU_SELECTED_COLOR),
+                        ));
+                    }
+                    for (player, name, score, _, stats) in players.iter() {
+                        let color = if winner == Some(*player) {
+                            MENU_SELECTED_COLOR
+                        } else {
+                            Color::WHITE
+                        };
+                        let mut cells = vec![
+                            name.0.to_uppercase(),
+                            score.value.to_string(),
+                            stats.max_length.to_string(),
+                            stats.apples.to_string(),
+                            stats.kills.to_string(),
+                            survived_label(stats.survived),
+                            cause_label(stats.cause_of_death, name_of),
+                        ];
+                        if let Some(versus) = versus.as_ref() {
+                            cells.insert(1, versus.wins(*player).to_string());
+                        }
+                        for cell in cells {
+                            grid.spawn(TextBundle::from_section(cell, text_style(24., color)));
+                        }
+                    }
+                });
+            spawn_menu_entries(builder, &labels, 0, font.clone());
+        });
+
+    commands.insert_resource(ResultsMenu {
+        cursor: MenuCursor::new(entries.len()),
+        entries,
+    });
+}
+
+pub fn results_input(
+    input: MenuInput,
+    mut menu: ResMut<ResultsMenu>,
+    mut entries: Query<(&mut Text, &MenuEntry)>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for action in input.actions() {
+        if menu.cursor.navigate(action) {
+            highlight_menu_entries(&menu.cursor, &mut entries);
+            continue;
+        }
+        if action != MenuAction::Select {
+            continue;
+        }
+        next_state.set(match menu.entries[menu.cursor.selected] {
+            // leaving and coming back starts the match over with the same players
+            ResultsEntry::Rematch => GameState::LeaveGame,
+            ResultsEntry::Lobby => GameState::EnterGame,
+            ResultsEntry::MainMenu => GameState::MainMenu,
+        });
+        return;
+    }
+}
@@ -0,0 +1,296 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{
+    menu::{MenuAction, MenuInput, MENU_SELECTED_COLOR},
+    netplay::NetSession,
+    GamePhase, GameState,
+};
+
+use super::components::{Dead, SnakeHead};
+use super::prelude::{Named, Player, SnakeHeadRef};
+use super::replay::Playback;
+use super::rules::GameRules;
+use super::spawner::BoardFull;
+
+/// How long the outcome of a round stays up before the next one starts.
+const ROUND_OVER_SECONDS: f32 = 3.;
+
+/**
+ * A versus match played over several rounds by last snake standing, with the rounds every player
+ * has won so far. Only there while such a match is going on; the same `Player` entities play every
+ * round of it.
+ */
+#[derive(Resource, Debug)]
+pub struct VersusMatch {
+    pub wins: HashMap<Entity, u32>,
+    pub rounds_played: u32,
+    /// The survivor of the last round, `None` when nobody survived it.
+    pub round_winner: Option<Entity>,
+    /// Rounds a player has to win to take the match.
+    pub wins_needed: u32,
+    pub best_of: u32,
+}
+
+impl VersusMatch {
+    pub fn new(best_of: u32) -> Self {
+        VersusMatch {
+            wins: HashMap::new(),
+            rounds_played: 0,
+            round_winner: None,
+            wins_needed: best_of.max(1) / 2 + 1,
+            best_of: best_of.max(1),
+        }
+    }
+
+    pub fn wins(&self, player: Entity) -> u32 {
+        self.wins.get(&player).copied().unwrap_or(0)
+    }
+
+    /// Scores a finished round, won by its `survivor` or drawn if there is none.
+    pub fn end_round(&mut self, survivor: Option<Entity>) {
+        self.rounds_played += 1;
+        self.round_winner = survivor;
+        if let Some(survivor) = survivor {
+            *self.wins.entry(survivor).or_default() += 1;
+        }
+    }
+
+    /**
+     * The player that has won enough rounds to take the match, if any. Drawn rounds count
+     * towards `best_of` too, after which whoever won the most rounds takes the match. Players
+     * still tied for the most after that play on in sudden death.
+     */
+    pub fn winner(&self) -> Option<Entity> {
+        let most = self.wins.values().copied().max()?;
+        let mut leaders = self.wins.iter().filter(|(_, wins)| **wins == most);
+        let (Some((leader, _)), None) = (leaders.next(), leaders.next()) else {
+            return None;
+        };
+        (most >= self.wins_needed || self.rounds_played >= self.best_of).then_some(*leader)
+    }
+}
+
+/// Set between two rounds of a `VersusMatch`, so the players are kept for the next one.
+#[derive(Resource)]
+pub struct NextRound(Timer);
+
+/**
+ * Starts a `VersusMatch` when a match with more than one player is played by last snake standing.
+ * Online matches always play until every snake is dead, and a replay only ever holds one round.
+ */
+pub fn start_versus_match(
+    mut commands: Commands,
+    rules: Res<GameRules>,
+    versus: Option<Res<VersusMatch>>,
+    next_round: Option<Res<NextRound>>,
+    net_session: Option<Res<NetSession>>,
+    playback: Option<Res<Playback>>,
+    players: Query<(), With<Player>>,
+) {
+    if next_round.is_some() {
+        commands.remove_resource::<NextRound>();
+        return;
+    }
+    if versus.is_some() {
+        return;
+    }
+    if rules.last_snake_standing && players.iter().len() > 1 && net_session.is_none() {
+        let best_of = if playback.is_some() { 1 } else { rules.best_of };
+        commands.insert_resource(VersusMatch::new(best_of));
+    }
+}
+
+/// A match that is left for good takes its round points with it.
+pub fn end_versus_match(mut commands: Commands) {
+    commands.remove_resource::<VersusMatch>();
+}
+
+/**
+ * Ends the round once at most one snake is left, or there is no room left for another apple.
+ * The survivor earns a round point, and the match is over once someone has won enough of them.
+ */
+pub fn check_round_over(
+    board_full: Res<BoardFull>,
+    playback: Option<Res<Playback>>,
+    mut versus: ResMut<VersusMatch>,
+    players: Query<(Entity, &SnakeHeadRef), With<Player>>,
+    heads: Query<Has<Dead>, With<SnakeHead>>,
+    mut next_phase: ResMut<NextState<GamePhase>>,
+) {
+    let alive: Vec<Entity> = players
+        .iter()
+        .filter(|(_, head_ref)| head_ref.0.and_then(|head| heads.get(head).ok()) == Some(false))
+        .map(|(player, _)| player)
+        .collect();
+    if alive.len() > 1 && !board_full.0 {
+        return;
+    }
+
+    versus.end_round(match alive[..] {
+        [survivor] => Some(survivor),
+        _ => None,
+    });
+    // a replay ends with the round it holds, drawn or not
+    if versus.winner().is_some() || playback.is_some() {
+        next_phase.set(GamePhase::Dead);
+    } else {
+        next_phase.set(GamePhase::RoundOver);
+    }
+}
+
+/**
+ * Shows who took the round and the standings of the match until the next round starts.
+ */
+pub fn spawn_round_over(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    versus: Res<VersusMatch>,
+    players: Query<(Entity, &Named), With<Player>>,
+) {
+    let font = asset_server.load("fonts/visitor.ttf");
+    let text_style = |font_size: f32, color: Color| TextStyle {
+        font_size,
+        color,
+        font: font.clone(),
+    };
+
+    let mut players: Vec<_> = players.iter().collect();
+    players.sort_by_key(|(player, _)| *player);
+    let title = versus
+        .round_winner
+        .and_then(|winner| players.iter().find(|(player, _)| *player == winner))
+        .map_or("NOBODY TAKES THE ROUND".to_string(), |(_, name)| {
+            format!("{} TAKES THE ROUND!", name.0.to_uppercase())
+        });
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(16.),
+                    ..default()
+                },
+                background_color: Color::BLACK.with_alpha(0.7).into(),
+                z_index: ZIndex::Global(4),
+                ..default()
+            },
+            StateScoped(GamePhase::RoundOver),
+        ))
+        .with_children(|builder| {
+            builder.spawn(TextBundle::from_section(
+                format!("ROUND {}", versus.rounds_played),
+                text_style(30., MENU_SELECTED_COLOR),
+            ));
+            builder.spawn(TextBundle::from_section(
+                title,
+                text_style(60., Color::WHITE),
+            ));
+            for (player, name) in players.iter() {
+                let color = if versus.round_winner == Some(*player) {
+                    MENU_SELECTED_COLOR
+                } else {
+                    Color::WHITE
+                };
+                builder.spawn(TextBundle::from_section(
+                    format!(
+                        "{}  {} / {}",
+                        name.0.to_uppercase(),
+                        versus.wins(*player),
+                        versus.wins_needed
+                    ),
+                    text_style(30., color),
+                ));
+            }
+            builder.spawn(TextBundle::from_section(
+                "ENTER OR A: NEXT ROUND",
+                text_style(20., Color::WHITE),
+            ));
+        });
+
+    commands.insert_resource(NextRound(Timer::from_seconds(
+        ROUND_OVER_SECONDS,
+        TimerMode::Once,
+    )));
+}
+
+/**
+ * Starts the next round once the outcome has been up for long enough, or straight away when
+ * asked to. Leaving and coming back sets up the board again for the same players.
+ */
+pub fn start_next_round(
+    time: Res<Time>,
+    input: MenuInput,
+    mut next_round: ResMut<NextRound>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    next_round.0.tick(time.delta());
+    let skipped = input.actions().contains(&MenuAction::Select);
+
+    if next_round.0.just_finished() || skipped {
+        next_state.set(GameState::LeaveGame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: Entity = Entity::from_raw(1);
+    const B: Entity = Entity::from_raw(2);
+
+    fn play(best_of: u32, rounds: &[Option<Entity>]) -> VersusMatch {
+        let mut versus = VersusMatch::new(best_of);
+        for survivor in rounds {
+            versus.end_round(*survivor);
+        }
+        versus
+    }
+
+    #[test]
+    fn a_majority_of_rounds_takes_the_match_early() {
+        assert_eq!(play(3, &[Some(A)]).winner(), None);
+        assert_eq!(play(3, &[Some(A), Some(A)]).winner(), Some(A));
+        assert_eq!(play(5, &[Some(A), Some(B), Some(A)]).winner(), None);
+        assert_eq!(
+            play(5, &[Some(A), Some(B), Some(A), Some(A)]).winner(),
+            Some(A)
+        );
+    }
+
+    #[test]
+    fn a_round_won_by_nobody_scores_no_point() {
+        let versus = play(3, &[None]);
+        assert_eq!(versus.rounds_played, 1);
+        assert_eq!(versus.round_winner, None);
+        assert_eq!((versus.wins(A), versus.wins(B)), (0, 0));
+        assert_eq!(versus.winner(), None);
+    }
+
+    #[test]
+    fn drawn_rounds_count_towards_best_of() {
+        assert_eq!(play(3, &[None, Some(B), None]).winner(), Some(B));
+    }
+
+    #[test]
+    fn a_tie_after_best_of_rounds_goes_to_sudden_death() {
+        let tied = play(3, &[Some(A), Some(B), None]);
+        assert_eq!(tied.winner(), None);
+        assert_eq!(play(3, &[Some(A), Some(B), None, None]).winner(), None);
+        assert_eq!(
+            play(3, &[Some(A), Some(B), None, Some(B)]).winner(),
+            Some(B)
+        );
+    }
+
+    #[test]
+    fn best_of_one_is_a_single_round() {
+        assert_eq!(play(1, &[Some(A)]).winner(), Some(A));
+        assert_eq!(play(0, &[Some(B)]).winner(), Some(B));
+    }
+}
@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_asset_loader::asset_collection::AssetCollection;
+use serde::{Deserialize, Serialize};
+
+use super::collision::CollisionRules;
+use super::{INITIAL_GAME_SPEED, TILE_SIZE, WORLD_SIZE_X, WORLD_SIZE_Y};
+
+/// What is left on the board by a snake that died.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DeadBodies {
+    /// The skeleton stays where the snake died, in the way of everyone else.
+    #[default]
+    Remain,
+    /// The skeleton fades away, and can be passed through straight away.
+    Fade,
+    /// Every cell of the snake turns into an apple.
+    Apples,
+}
+
+/**
+ * The tunables of a match, read from `assets/rules/default.rules.json`.
+ * Any field missing from the file keeps its default value. Changes to the file are picked up
+ * while the game runs; speed and growth apply immediately, the rest from the next match.
+ */
+#[derive(Asset, Resource, TypePath, Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct GameRules {
+    /// Ticks per second at the start of a match.
+    pub initial_speed: f64,
+    /// The tick length is multiplied by this every time an apple is eaten.
+    pub speed_up_per_apple: f32,
+    /// Ticks per second the game never speeds up beyond.
+    pub max_speed: f64,
+    /// Segments added to a snake per apple eaten.
+    pub growth_per_apple: u32,
+    /// Body segments behind the head when a snake spawns.
+    pub starting_length: u32,
+    /// How many times likelier an apple is to spawn on an apple zone tile than on plain floor.
+    pub apple_zone_weight: u32,
+    /// Size in cells of the open arena. Arenas loaded from level files bring their own size.
+    pub arena_width: i32,
+    pub arena_height: i32,
+    /// Size in pixels of a grid cell.
+    pub tile_size: f32,
+    pub collisions: CollisionRules,
+    pub dead_bodies: DeadBodies,
+    /// With more than one player, a round ends as soon as one snake is left and earns it a point.
+    pub last_snake_standing: bool,
+    /// Rounds in a match played by last snake standing. Whoever wins most of them wins the match,
+    /// with sudden death rounds as long as that is a tie.
+    pub best_of: u32,
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        GameRules {
+            initial_speed: INITIAL_GAME_SPEED,
+            speed_up_per_apple: 0.95,
+            max_speed: 30.,
+            growth_per_apple: 1,
+            starting_length: 3,
+            apple_zone_weight: 4,
+            arena_width: WORLD_SIZE_X as i32,
+            arena_height: WORLD_SIZE_Y as i32,
+            tile_size: TILE_SIZE,
+            collisions: CollisionRules::default(),
+            dead_bodies: DeadBodies::default(),
+            last_snake_standing: false,
+            best_of: 1,
+        }
+    }
+}
+
+impl GameRules {
+    /// The tick length after eating an apple at the current tick length.
+    pub fn sped_up(&self, timestep: Duration) -> Duration {
+        let fastest = Duration::from_secs_f64(1. / self.max_speed);
+        timestep.mul_f32(self.speed_up_per_apple).max(fastest)
+    }
+}
+
+#[derive(AssetCollection, Resource)]
+pub struct RulesAssets {
+    #[asset(path = "rules/default.rules.json")]
+    pub rules: Handle<GameRules>,
+}
+
+/**
+ * Copies the rules asset into the `GameRules` resource once it is loaded
+ * and again whenever it is edited on disk.
+ */
+pub fn sync_game_rules(
+    mut events: EventReader<AssetEvent<GameRules>>,
+    rules_assets: Option<Res<RulesAssets>>,
+    assets: Res<Assets<GameRules>>,
+    mut rules: ResMut<GameRules>,
+) {
+    let Some(rules_assets) = rules_assets else {
+        return;
+    };
+    let id = rules_assets.rules.id();
+    // the initial load finishes before the loading state inserts `RulesAssets`
+    let changed = rules_assets.is_added()
+        | events
+            .read()
+            .any(|event| event.is_loaded_with_dependencies(id) || event.is_modified(id));
+
+    if let (true, Some(loaded)) = (changed, assets.get(id)) {
+        info!("game rules loaded");
+        *rules = loaded.clone();
+    }
+}
@@ -0,0 +1,119 @@
+use bevy::prelude::*;
+use bevy_turborand::{DelegatedRng, GlobalRng};
+
+use super::level::{Level, LevelTile};
+use super::occupancy::Occupancy;
+
+/// Set when an apple could not be placed because every free cell is taken.
+#[derive(Resource, Default)]
+pub struct BoardFull(pub bool);
+
+/**
+ * Picks the cell for the next apple among the free cells of the arena. Every free floor cell is
+ * equally likely, apple zone tiles count `zone_weight` times and portals are never picked.
+ * Returns `None` when there is no free cell left.
+ */
+pub fn pick_apple_cell(
+    rng: &mut GlobalRng,
+    occupancy: &Occupancy,
+    level: &Level,
+    zone_weight: u32,
+) -> Option<IVec2> {
+    let candidates: Vec<(IVec2, u32)> = level
+        .cells()
+        .filter(|(pos, _)| occupancy.is_free(*pos))
+        .filter_map(|(pos, tile)| match tile {
+            LevelTile::Floor => Some((pos, 1)),
+            LevelTile::AppleZone => Some((pos, zone_weight)),
+            LevelTile::Wall | LevelTile::Portal(_) => None,
+        })
+        .filter(|(_, weight)| *weight > 0)
+        .collect();
+
+    let total: u32 = candidates.iter().map(|(_, weight)| weight).sum();
+    if total == 0 {
+        return None;
+    }
+
+    let mut roll = rng.u32(0..total);
+    for (pos, weight) in candidates {
+        if roll < weight {
+            return Some(pos);
+        }
+        roll -= weight;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::grid::Grid;
+    use crate::game::occupancy::Occupant;
+
+    /// The level in `text` with its walls taken, like `load_level` leaves it.
+    fn board(text: &str) -> (Level, Occupancy) {
+        let level = Level::parse(text).unwrap();
+        let mut occupancy = Occupancy::new(&Grid {
+            width: level.width,
+            height: level.height,
+            ..default()
+        });
+        for (pos, tile) in level.cells() {
+            if tile == LevelTile::Wall {
+                occupancy.set(pos, Occupant::Wall);
+            }
+        }
+        (level, occupancy)
+    }
+
+    fn picks(level: &Level, occupancy: &Occupancy, zone_weight: u32) -> Vec<IVec2> {
+        let mut rng = GlobalRng::with_seed(3);
+        (0..1000)
+            .map(|_| pick_apple_cell(&mut rng, occupancy, level, zone_weight).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn zone_tiles_count_zone_weight_times() {
+        let (level, occupancy) = board(".*");
+        let zone = IVec2::new(1, 0);
+
+        let in_zone = picks(&level, &occupancy, 3)
+            .iter()
+            .filter(|&&pos| pos == zone)
+            .count();
+        assert!(
+            (700..800).contains(&in_zone),
+            "{in_zone} of 1000 in the zone"
+        );
+
+        assert!(!picks(&level, &occupancy, 0).contains(&zone));
+    }
+
+    #[test]
+    fn only_free_floor_is_picked() {
+        let (level, mut occupancy) = board("A.#A\n*..*");
+        occupancy.set(IVec2::new(1, 0), Occupant::Apple(Entity::PLACEHOLDER));
+        occupancy.set(IVec2::new(2, 0), Occupant::Wall);
+        occupancy.set(IVec2::new(3, 0), Occupant::Body(Entity::PLACEHOLDER));
+
+        let mut picked = picks(&level, &occupancy, 1);
+        picked.sort_by_key(|pos| (pos.x, pos.y));
+        picked.dedup();
+        assert_eq!(picked, vec![IVec2::new(0, 0), IVec2::new(1, 1)]);
+    }
+
+    #[test]
+    fn full_board_has_no_cell() {
+        let mut rng = GlobalRng::with_seed(3);
+        let (level, mut occupancy) = board("#.\n*#");
+        occupancy.set(IVec2::new(0, 0), Occupant::Head(Entity::PLACEHOLDER));
+        occupancy.set(IVec2::new(1, 1), Occupant::Apple(Entity::PLACEHOLDER));
+
+        assert_eq!(pick_apple_cell(&mut rng, &occupancy, &level, 2), None);
+
+        let (walls, occupancy) = board("##\n##");
+        assert_eq!(pick_apple_cell(&mut rng, &occupancy, &walls, 2), None);
+    }
+}
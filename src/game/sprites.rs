@@ -0,0 +1,135 @@
+use bevy::prelude::*;
+use bevy_tween::tween::AnimationTarget;
+
+use super::components::{Apple, SnakeBodyPart, SnakeHead};
+use super::death::NewlyDead;
+use super::grid::{Grid, GridPos};
+use super::prelude::{BodyRef, SnakeTextureIndex};
+
+const SPLAT_SIZE: f32 = 2.;
+/// One skin per row of `sneks.png`, not counting the skeleton in the last row.
+pub const SKIN_COUNT: usize = 4;
+const SKELETON_SKIN: usize = 4;
+
+/**
+ * Texture handles for everything the simulation spawns. Each row of `sneks.png` is one snake skin
+ * with the tail, body and head in that order, and the last row is what is left of a dead snake.
+ */
+#[derive(Resource)]
+pub struct GameSprites {
+    snake_texture: Handle<Image>,
+    snake_layout: Handle<TextureAtlasLayout>,
+    apple_texture: Handle<Image>,
+    apple_layout: Handle<TextureAtlasLayout>,
+}
+
+impl FromWorld for GameSprites {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.resource::<AssetServer>();
+        let snake_texture = asset_server.load("textures/chars/sneks.png");
+        let apple_texture = asset_server.load("textures/chars/char_atlas.png");
+
+        let mut texture_atlases = world.resource_mut::<Assets<TextureAtlasLayout>>();
+        let snake_layout = texture_atlases.add(TextureAtlasLayout::from_grid(
+            UVec2::new(16, 16),
+            3,
+            5,
+            None,
+            None,
+        ));
+        let apple_layout = texture_atlases.add(TextureAtlasLayout::from_grid(
+            UVec2::new(16, 16),
+            4,
+            2,
+            None,
+            None,
+        ));
+
+        GameSprites {
+            snake_texture,
+            snake_layout,
+            apple_texture,
+            apple_layout,
+        }
+    }
+}
+
+impl GameSprites {
+    /// The head of a snake skin, for showing it in menus.
+    pub fn snake_icon(&self, skin: usize) -> (UiImage, TextureAtlas) {
+        (
+            UiImage::new(self.snake_texture.clone()),
+            TextureAtlas {
+                layout: self.snake_layout.clone(),
+                index: 2 + 3 * skin,
+            },
+        )
+    }
+}
+
+fn sprite_bundle(texture: &Handle<Image>, grid: &Grid, pos: &GridPos) -> SpriteBundle {
+    SpriteBundle {
+        texture: texture.clone(),
+        transform: Transform::from_translation(grid.to_world(pos.0).extend(0.))
+            .with_scale(Vec3::splat(SPLAT_SIZE)),
+        ..Default::default()
+    }
+}
+
+type NewSnakeParts<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static GridPos,
+        &'static SnakeTextureIndex,
+        Has<SnakeHead>,
+    ),
+    Or<(Added<SnakeHead>, Added<SnakeBodyPart>)>,
+>;
+
+/**
+ * Gives newly spawned snake parts and apples their sprite.
+ */
+pub fn attach_sprites(
+    mut commands: Commands,
+    grid: Res<Grid>,
+    sprites: Res<GameSprites>,
+    snake_parts: NewSnakeParts,
+    apples: Query<(Entity, &GridPos), Added<Apple>>,
+) {
+    for (entity, pos, texture_index, is_head) in snake_parts.iter() {
+        let column = if is_head { 2 } else { 1 };
+        commands.entity(entity).insert((
+            TextureAtlas {
+                layout: sprites.snake_layout.clone(),
+                index: column + (3 * texture_index.0),
+            },
+            sprite_bundle(&sprites.snake_texture, &grid, pos),
+            AnimationTarget,
+        ));
+    }
+
+    for (entity, pos) in apples.iter() {
+        commands.entity(entity).insert((
+            TextureAtlas {
+                layout: sprites.apple_layout.clone(),
+                index: 7,
+            },
+            sprite_bundle(&sprites.apple_texture, &grid, pos),
+        ));
+    }
+}
+
+/**
+ * Turns the sprites of a snake that just died into its skeleton.
+ */
+pub fn show_skeletons(heads: NewlyDead<(Entity, &BodyRef)>, mut atlases: Query<&mut TextureAtlas>) {
+    for (head, body_ref) in heads.iter() {
+        for part in std::iter::once(head).chain(body_ref.0.iter().copied()) {
+            if let Ok(mut atlas) = atlases.get_mut(part) {
+                atlas.index = atlas.index % 3 + 3 * SKELETON_SKIN;
+            }
+        }
+    }
+}
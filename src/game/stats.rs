@@ -0,0 +1,199 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use super::collision::{CollisionEvent, CollisionKind, CollisionOutcome};
+use super::components::Dead;
+use super::prelude::{BodyRef, Player, SnakeHeadRef};
+
+/// What ended a snake. Other snakes are named by their head.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeathCause {
+    Wall,
+    SelfBite,
+    /// Ran into the body of another snake.
+    RanInto(Entity),
+    /// Had its body bitten by another snake.
+    BittenBy(Entity),
+    HeadOn(Entity),
+}
+
+impl DeathCause {
+    /// The other snake involved, if any.
+    pub fn other(&self) -> Option<Entity> {
+        match self {
+            DeathCause::Wall | DeathCause::SelfBite => None,
+            DeathCause::RanInto(other)
+            | DeathCause::BittenBy(other)
+            | DeathCause::HeadOn(other) => Some(*other),
+        }
+    }
+}
+
+/**
+ * How a player has done in the current match, on top of its `Score`. Reset whenever a match
+ * starts; over the rounds of a versus match only the cause of death is.
+ */
+#[derive(Component, Debug, Clone, Default)]
+pub struct PlayerStats {
+    pub max_length: usize,
+    pub apples: u32,
+    /// Other snakes that died running into this one, being bitten by it or head on with it.
+    pub kills: u32,
+    pub cause_of_death: Option<DeathCause>,
+    /// Match time spent alive, not counting pauses.
+    pub survived: Duration,
+}
+
+/**
+ * The player that did best in a match with more than one player, given each player's stats and
+ * score: the last snake standing, and the higher score of those that went down together. `None`
+ * for a draw or a single player. The results screen and headless matches both go by this.
+ */
+pub fn match_winner<'a, K: Copy>(
+    players: impl IntoIterator<Item = (K, &'a PlayerStats, u32)>,
+) -> Option<K> {
+    let ranked: Vec<(K, (Duration, u32))> = players
+        .into_iter()
+        .map(|(player, stats, score)| (player, (stats.survived, score)))
+        .collect();
+    if ranked.len() < 2 {
+        return None;
+    }
+    let best = ranked.iter().map(|(_, rank)| *rank).max()?;
+    let mut best_players = ranked.iter().filter(|(_, rank)| *rank == best);
+    match (best_players.next(), best_players.next()) {
+        (Some((player, _)), None) => Some(*player),
+        _ => None,
+    }
+}
+
+/**
+ * Adds the tick to the time survived of every snake still alive, and keeps the longest each
+ * snake has been.
+ */
+pub fn track_survival(
+    time: Res<Time>,
+    mut players: Query<(&SnakeHeadRef, &mut PlayerStats), With<Player>>,
+    heads: Query<(&BodyRef, Has<Dead>)>,
+) {
+    for (head_ref, mut stats) in players.iter_mut() {
+        let Some(Ok((body_ref, dead))) = head_ref.0.map(|head| heads.get(head)) else {
+            continue;
+        };
+        stats.max_length = stats.max_length.max(body_ref.0.len() + 1);
+        if !dead {
+            stats.survived += time.delta();
+        }
+    }
+}
+
+/**
+ * Writes down the cause of every death reported by `resolve_collisions`, and credits the kill
+ * to the other snake involved as long as it was still alive.
+ */
+pub fn track_deaths(
+    mut collisions: EventReader<CollisionEvent>,
+    mut players: Query<(&SnakeHeadRef, &mut PlayerStats), With<Player>>,
+) {
+    for ev in collisions.read() {
+        let (biter_dies, victim_dies) = match ev.outcome {
+            CollisionOutcome::BiterDied => (true, false),
+            CollisionOutcome::VictimDied => (false, true),
+            CollisionOutcome::BothDied => (true, true),
+            CollisionOutcome::Cut { .. } | CollisionOutcome::Stolen { .. } => (false, false),
+        };
+        let mut deaths = Vec::new();
+        if biter_dies {
+            deaths.push((
+                ev.biter,
+                match ev.kind {
+                    CollisionKind::Wall => DeathCause::Wall,
+                    CollisionKind::SelfBite => DeathCause::SelfBite,
+                    CollisionKind::BodyBite { victim } => DeathCause::RanInto(victim),
+                    CollisionKind::HeadOn { other } => DeathCause::HeadOn(other),
+                },
+            ));
+        }
+        if victim_dies {
+            match ev.kind {
+                CollisionKind::BodyBite { victim } => {
+                    deaths.push((victim, DeathCause::BittenBy(ev.biter)))
+                }
+                CollisionKind::HeadOn { other } => {
+                    deaths.push((other, DeathCause::HeadOn(ev.biter)))
+                }
+                CollisionKind::Wall | CollisionKind::SelfBite => {}
+            }
+        }
+
+        let died_now = |head: Entity| deaths.iter().any(|(dead, _)| *dead == head);
+        let mut credited = Vec::new();
+        for (head, cause) in deaths.iter() {
+            let Some((_, mut stats)) = players
+                .iter_mut()
+                .find(|(head_ref, _)| head_ref.0 == Some(*head))
+            else {
+                continue;
+            };
+            stats.cause_of_death = Some(*cause);
+            if let Some(other) = cause.other() {
+                credited.push(other);
+            }
+        }
+        for other in credited {
+            let Some((_, mut stats)) = players
+                .iter_mut()
+                .find(|(head_ref, _)| head_ref.0 == Some(other))
+            else {
+                continue;
+            };
+            // running into a snake that is already dead isn't its kill
+            if stats.cause_of_death.is_none() || died_now(other) {
+                stats.kills += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn survived(millis: u64) -> PlayerStats {
+        PlayerStats {
+            survived: Duration::from_millis(millis),
+            ..default()
+        }
+    }
+
+    #[test]
+    fn the_last_snake_standing_wins_whatever_the_score() {
+        let (first, last) = (survived(500), survived(750));
+        assert_eq!(match_winner([(0, &first, 9), (1, &last, 1)]), Some(1));
+    }
+
+    #[test]
+    fn snakes_that_went_down_together_go_by_score() {
+        let (a, b, c) = (survived(500), survived(500), survived(250));
+        assert_eq!(match_winner([(0, &a, 3), (1, &b, 4), (2, &c, 10)]), Some(1));
+    }
+
+    #[test]
+    fn a_tie_on_time_and_score_is_a_draw() {
+        let (a, b) = (survived(500), survived(500));
+        assert_eq!(match_winner([(0, &a, 4), (1, &b, 4)]), None);
+    }
+
+    #[test]
+    fn a_draw_at_the_top_is_not_won_by_anyone_below() {
+        let (a, b, c) = (survived(500), survived(500), survived(500));
+        assert_eq!(match_winner([(0, &a, 4), (1, &b, 4), (2, &c, 1)]), None);
+    }
+
+    #[test]
+    fn a_single_player_has_no_winner() {
+        let alone = survived(500);
+        assert_eq!(match_winner([(0, &alone, 4)]), None);
+    }
+}
@@ -1,88 +1,59 @@
 use bevy::prelude::*;
-use bevy_egui::egui::style;
-use bevy_turborand::DelegatedRng;
 use bevy_turborand::{GlobalRng, RngComponent};
-use bevy_tween::tween::{AnimationTarget, IntoTarget};
 
+use crate::config::Seed;
+use crate::gamepad::any_gamepad_just_pressed;
 use crate::{GamePhase, GameState, SCREEN};
 
-use super::collision::circles_touching;
+use super::collision::{CollisionEvent, CollisionKind, CollisionOutcome};
 use super::components::{
-    Apple, Bounding, Collidible, Dead, ExampleGameText, GameEntityRef, GrowSnakeEvent,
-    MoveAppleEvent, PausedText, Pos, ScoreText, SnakeBodyPart, SnakeHead, Tail, Vel,
+    Apple, BonusApple, Collidible, Dead, DebugText, ElapsedTicks, ExampleGameText, GameEntityRef,
+    GrowSnakeEvent, MoveAppleEvent, Pos, ScoreText, SnakeBodyPart, SnakeHead, Tail, Vel,
 };
+use super::effects::Flick;
+use super::grid::{Grid, GridPos};
+use super::level::ActiveLevel;
+use super::occupancy::{Occupancy, Occupant};
 use super::prelude::{
-    BodyRef, ControlScheme, Player, Score, SnakeDirection, SnakeHeadRef, SnakeTextureIndex,
+    BodyRef, ControlScheme, DirectionQueue, Player, Score, SnakeDirection, SnakeHeadRef,
+    SnakeTextureIndex,
 };
-use super::INITIAL_GAME_SPEED;
-
-const TILE_SIZE: f32 = 32.;
-const SPLAT_SIZE: f32 = 2.;
-const WORLD_SIZE_X: u32 = 40;
-const WORLD_SIZE_Y: u32 = 22;
-// const TILE_SIZE: f32 = 16.;
-// const WORLD_SIZE_X: u32 = 80;
-// const WORLD_SIZE_Y: u32 = 45;
+use super::rounds::{NextRound, VersusMatch};
+use super::rules::GameRules;
+use super::spawner::{pick_apple_cell, BoardFull};
+use super::stats::PlayerStats;
 
 pub fn pause_controls(
     keyboard: Res<ButtonInput<KeyCode>>,
+    gamepad_buttons: Res<ButtonInput<GamepadButton>>,
     curr_state: Res<State<GamePhase>>,
     mut next_state: ResMut<NextState<GamePhase>>,
-    mut pause_texts: Query<&mut Visibility, With<PausedText>>,
 ) {
-    if keyboard.just_pressed(KeyCode::KeyP) {
-        let mut new_state = curr_state.clone();
+    if keyboard.just_pressed(KeyCode::KeyP)
+        || any_gamepad_just_pressed(&gamepad_buttons, GamepadButtonType::Start)
+    {
         match curr_state.get() {
-            GamePhase::Playing => {
-                new_state = GamePhase::Paused;
-                next_state.set(new_state.clone());
-            }
-            GamePhase::Paused => {
-                new_state = GamePhase::Playing;
-                next_state.set(new_state.clone());
-            }
+            GamePhase::Playing => next_state.set(GamePhase::Paused),
+            GamePhase::Paused => next_state.set(GamePhase::Playing),
             _ => {}
         }
-        if next_state.is_changed() {
-            for mut vis in pause_texts.iter_mut() {
-                match new_state {
-                    GamePhase::Playing => *vis = Visibility::Hidden,
-                    GamePhase::Paused => *vis = Visibility::Inherited,
-                    _ => {}
-                }
-            }
-        }
-    }
-}
-
-fn valid_direction(prev: &SnakeDirection, new: &SnakeDirection) -> bool {
-    match prev {
-        SnakeDirection::Left => *new != SnakeDirection::Right,
-        SnakeDirection::Right => *new != SnakeDirection::Left,
-        SnakeDirection::Up => *new != SnakeDirection::Down,
-        SnakeDirection::Down => *new != SnakeDirection::Up,
     }
 }
 
 pub fn game_keys(
     keyboard: Res<ButtonInput<KeyCode>>,
-    players: Query<(&ControlScheme, &SnakeHeadRef), With<Player>>,
-    mut snake_heads: Query<&mut SnakeHead>,
+    mut players: Query<(&ControlScheme, &mut DirectionQueue, &SnakeHeadRef), With<Player>>,
+    snake_heads: Query<&SnakeHead>,
 ) {
-    for (controls, snake_head_ref) in players.iter() {
-        let mut direction = None;
-        for key in keyboard.get_just_pressed() {
-            direction = controls.direction_changed(key);
-        }
-
-        if let Some(direction) = direction {
-            let mut snake_head = snake_heads.get_mut(snake_head_ref.0.unwrap()).unwrap();
+    for (controls, mut queue, snake_head_ref) in players.iter_mut() {
+        let Some(Ok(snake_head)) = snake_head_ref.0.map(|head| snake_heads.get(head)) else {
+            continue;
+        };
 
-            if !valid_direction(&snake_head.direction, &direction) {
-                continue;
+        for key in keyboard.get_just_pressed() {
+            if let Some(direction) = controls.direction_changed(key) {
+                queue.push(direction, &snake_head.direction);
             }
-
-            snake_head.direction = direction.clone();
         }
     }
 
@@ -102,52 +73,94 @@ pub fn game_keys(
     // }
 }
 
-fn get_random_point() -> Vec2 {
-    let mut rng = GlobalRng::new();
-    let x_tiles = WORLD_SIZE_X as f32 / 2.;
-    let y_tiles = WORLD_SIZE_Y as f32 / 2.;
-    let x = rng.f32() * x_tiles * TILE_SIZE;
-    let x_values = [x, -x];
-    let x = rng.sample(&x_values);
-
-    let y = rng.f32() * y_tiles * TILE_SIZE;
-    let y_values = [y, -y];
-    let y = rng.sample(&y_values);
-
-    Vec2::new(*x.unwrap(), *y.unwrap())
-}
+/// The apple every snake is after, leaving out the bonus apples dead snakes leave behind.
+type AppleQuery<'w, 's> =
+    Query<'w, 's, (Entity, &'static mut GridPos), (With<Apple>, Without<BonusApple>)>;
 
+/**
+ * Moves the apple to a new free cell, at the start of a match and after every eat.
+ * When there is no free cell left the apple is removed and the board is full.
+ */
+#[allow(clippy::too_many_arguments)]
 pub fn move_apple_handler(
+    mut commands: Commands,
+    level: Res<ActiveLevel>,
+    rules: Res<GameRules>,
+    mut rng: ResMut<GlobalRng>,
+    mut occupancy: ResMut<Occupancy>,
+    mut board_full: ResMut<BoardFull>,
     mut ev_spawn_apple: EventReader<MoveAppleEvent>,
-    mut apple_query: Query<(Entity, &mut Transform), With<Apple>>,
+    mut apple_query: AppleQuery,
 ) {
     for _ in ev_spawn_apple.read() {
-        let p = get_random_point();
-        let mut apple = apple_query.get_single_mut().unwrap();
-        apple.1.translation = p.extend(0.);
+        let Ok((apple_entity, mut apple_pos)) = apple_query.get_single_mut() else {
+            continue;
+        };
+        occupancy.clear_if(apple_pos.0, Occupant::Apple(apple_entity));
+
+        match pick_apple_cell(&mut rng, &occupancy, &level, rules.apple_zone_weight) {
+            Some(p) => {
+                apple_pos.0 = p;
+                occupancy.set(p, Occupant::Apple(apple_entity));
+            }
+            None => {
+                board_full.0 = true;
+                commands.entity(apple_entity).despawn_recursive();
+            }
+        }
     }
 }
 
+/// Shows every player's score, and the rounds it has won in a versus match.
 pub fn update_score_text(
+    versus: Option<Res<VersusMatch>>,
     player_query: Query<&Score, With<Player>>,
     mut text_query: Query<(&mut Text, &GameEntityRef), With<ScoreText>>,
 ) {
     for (mut text, ge_ref) in text_query.iter_mut() {
         let score = player_query.get(ge_ref.0).unwrap();
-        text.sections[0].value = score.value.to_string();
+        text.sections[0].value = match versus.as_ref() {
+            Some(versus) => format!("{}  WINS {}", score.value, versus.wins(ge_ref.0)),
+            None => score.value.to_string(),
+        };
     }
 }
 
+/**
+ * Moves on to the next seed once a match or round is over, so the next one, or a rematch, gets
+ * apples of its own. Replays keep the seed they were recorded with.
+ */
+pub fn advance_seed(mut seed: ResMut<Seed>) {
+    *seed = seed.next();
+}
+
+/**
+ * Resets the tick rate and restarts the random number generator from the match seed,
+ * so everything random in the match follows from `Seed`.
+ */
+pub fn init_match(
+    rules: Res<GameRules>,
+    seed: Res<Seed>,
+    mut rng: ResMut<GlobalRng>,
+    mut board_full: ResMut<BoardFull>,
+    mut elapsed_ticks: ResMut<ElapsedTicks>,
+    mut fixed_time: ResMut<Time<Fixed>>,
+) {
+    fixed_time.set_timestep_hz(rules.initial_speed);
+    board_full.0 = false;
+    elapsed_ticks.0 = 0;
+    *rng = GlobalRng::with_seed(seed.0);
+}
+
+pub fn count_tick(mut elapsed_ticks: ResMut<ElapsedTicks>) {
+    elapsed_ticks.0 += 1;
+}
+
 pub fn init_game(
     mut commands: Commands,
     score_query: Query<(Entity, &Score)>,
     asset_server: Res<AssetServer>,
-    mut fixed_time: ResMut<Time<Fixed>>,
 ) {
-    fixed_time.set_timestep_hz(INITIAL_GAME_SPEED);
-
-    let score_len = score_query.iter().len();
-
     // Score Text
     commands
         .spawn((
@@ -213,105 +226,134 @@ pub fn init_game(
         });
 }
 
+/**
+ * The cells the body of a new snake heading right starts on, one per segment going left from
+ * `head`. Segments that would run into something stay on the last free cell instead, the way
+ * freshly grown tails do, and unfold as the snake moves.
+ */
+fn body_cells(grid: &Grid, occupancy: &Occupancy, head: IVec2, segments: u32) -> Vec<IVec2> {
+    let mut cell = head;
+    (0..segments)
+        .map(|_| {
+            let behind = grid.step(cell, &SnakeDirection::Left);
+            if occupancy.is_free(behind) {
+                cell = behind;
+            }
+            cell
+        })
+        .collect()
+}
+
+/**
+ * Where a new snake starts: the `preferred` cell if the whole snake fits there, or else the cell
+ * closest to the center of the grid that it does fit at. Failing that any free cell will do, and
+ * `None` means the board is full.
+ */
+fn spawn_cell(
+    grid: &Grid,
+    occupancy: &Occupancy,
+    preferred: IVec2,
+    segments: u32,
+) -> Option<IVec2> {
+    let fits = |head: IVec2| {
+        let mut cell = head;
+        occupancy.is_free(head)
+            && (0..segments).all(|_| {
+                cell = grid.step(cell, &SnakeDirection::Left);
+                cell != head && occupancy.is_free(cell)
+            })
+    };
+    let center = grid.center();
+    let mut cells: Vec<IVec2> = (0..grid.height)
+        .flat_map(|y| (0..grid.width).map(move |x| IVec2::new(x, y)))
+        .collect();
+    cells.sort_by_key(|cell| (*cell - center).abs().element_sum());
+
+    let candidates = std::iter::once(preferred).chain(cells.iter().copied());
+    candidates
+        .clone()
+        .find(|cell| fits(*cell))
+        .or_else(|| candidates.clone().find(|cell| occupancy.is_free(*cell)))
+}
+
+type SnakePlayers<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static mut SnakeHeadRef,
+        &'static mut DirectionQueue,
+        &'static SnakeTextureIndex,
+        Option<&'static mut PlayerStats>,
+    ),
+    With<Player>,
+>;
+
+#[allow(clippy::too_many_arguments)]
 pub fn setup_players(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut snake_players: Query<(Entity, &mut SnakeHeadRef, &SnakeTextureIndex), With<Player>>,
-    mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
+    grid: Res<Grid>,
+    level: Res<ActiveLevel>,
+    rules: Res<GameRules>,
+    next_round: Option<Res<NextRound>>,
+    mut occupancy: ResMut<Occupancy>,
+    mut spawn_apple: EventWriter<MoveAppleEvent>,
+    mut snake_players: SnakePlayers,
 ) {
-    let snake_texture = asset_server.load("textures/chars/sneks.png");
-    let snake_layout = TextureAtlasLayout::from_grid(UVec2::new(16, 16), 3, 5, None, None);
-    let snake_atlas_layout = texture_atlases.add(snake_layout);
-
-    let head_sprite = AnimationTarget.into_target();
-
-    let mut head_pos = Transform::IDENTITY;
-    println!("spawning player");
-    for (i, (_, mut snake_head_ref, texture_index)) in snake_players.iter_mut().enumerate() {
-        head_pos = Transform::from_translation(Vec3::new(0.0, i as f32 * TILE_SIZE * 2., 0.))
-            .with_scale(Vec3::splat(SPLAT_SIZE));
-        println!("spawned player");
+    let segments = rules.starting_length.max(1);
+    for (i, (player, mut snake_head_ref, mut direction_queue, texture_index, stats)) in
+        snake_players.iter_mut().enumerate()
+    {
+        direction_queue.0.clear();
+        // the stats of a versus match add up over all of its rounds
+        match (stats, next_round.is_some()) {
+            (Some(mut stats), true) => stats.cause_of_death = None,
+            _ => {
+                commands.entity(player).insert(PlayerStats::default());
+            }
+        }
+        let preferred = level
+            .spawns
+            .get(i)
+            .copied()
+            .unwrap_or(grid.center() + IVec2::new(0, i as i32 * 2));
+        let Some(head_cell) = spawn_cell(&grid, &occupancy, preferred, segments) else {
+            warn!("no room left for the snake of player {}", i + 1);
+            *snake_head_ref = SnakeHeadRef(None);
+            continue;
+        };
         // spawn head
         let head_entity = commands
             .spawn((
-                TextureAtlas {
-                    layout: snake_atlas_layout.clone(),
-                    index: 2 + (3 * texture_index.0),
-                    ..Default::default()
-                },
-                SpriteBundle {
-                    texture: snake_texture.clone(),
-                    transform: head_pos.clone(),
-                    ..Default::default()
-                },
-                AnimationTarget,
                 SnakeHead {
                     direction: SnakeDirection::Right,
                 },
-                Bounding(TILE_SIZE / 2.),
+                GridPos(head_cell),
+                *texture_index,
                 StateScoped(GameState::InGame),
             ))
             .id();
         *snake_head_ref = SnakeHeadRef(Some(head_entity));
-        head_pos.translation.x -= TILE_SIZE;
 
+        // spawn body, trailing behind the head
+        let body_cells = body_cells(&grid, &occupancy, head_cell, segments);
         let mut body_ref = Vec::new();
-        // spawn body
-        for _ in 0..2 {
-            let id = spawn_body_part(
-                &head_entity,
-                &mut commands,
-                &snake_atlas_layout,
-                1 + (3 * texture_index.0),
-                &snake_texture,
-                &head_pos,
-            );
+        for cell in body_cells {
+            occupancy.set(cell, Occupant::Body(head_entity));
+            let id = spawn_body_part(&mut commands, head_entity, *texture_index, GridPos(cell));
             body_ref.push(id);
-            // head_pos.translation.x -= TILE_SIZE;
         }
-        let tail_entity = spawn_body_part(
-            &head_entity,
-            &mut commands,
-            &snake_atlas_layout,
-            // 3 need to make more textures,
-            1 + (3 * texture_index.0),
-            &snake_texture,
-            &head_pos,
-        );
-        body_ref.push(tail_entity);
+        // after the body, which may share the head's cell when there is no room behind it
+        occupancy.set(head_cell, Occupant::Head(head_entity));
+        if let Some(tail_entity) = body_ref.last() {
+            commands.entity(*tail_entity).insert(Tail);
+        }
         commands.entity(head_entity).insert(BodyRef(body_ref));
-
-        commands.entity(tail_entity).insert(Tail);
     }
 
-    // apple
-    let apple_texture = asset_server.load("textures/chars/char_atlas.png");
-    let apple_layout = TextureAtlasLayout::from_grid(UVec2::new(16, 16), 4, 2, None, None);
-    let apple_atlas_layout = texture_atlases.add(apple_layout);
-    let p = get_random_point();
-    commands.spawn((
-        TextureAtlas {
-            layout: apple_atlas_layout.clone(),
-            index: 7,
-            ..Default::default()
-        },
-        SpriteBundle {
-            texture: apple_texture.clone(),
-            transform: Transform::from_translation(p.extend(0.))
-                .with_scale(Vec3::splat(SPLAT_SIZE)),
-            ..Default::default()
-        },
-        Apple,
-        Bounding(8.),
-        StateScoped(GameState::InGame),
-    ));
-    // .animation()
-    // .repeat(Repeat::Infinitely)
-    // .insert_tween_here(
-    //     Duration::from_millis(500),
-    //     EaseFunction::Linear,
-    //     head_sprite.with(atlas_index(0, 2)),
-    // );
+    // apple, placed by `move_apple_handler` once the snakes are on the board
+    commands.spawn((Apple, GridPos(IVec2::ZERO), StateScoped(GameState::InGame)));
+    spawn_apple.send(MoveAppleEvent);
 }
 
 pub fn tear_down_players(player_query: Query<Entity, With<Player>>, mut commands: Commands) {
@@ -320,213 +362,233 @@ pub fn tear_down_players(player_query: Query<Entity, With<Player>>, mut commands
     }
 }
 
+type LiveHeads<'w, 's> = Query<
+    'w,
+    's,
+    (&'static mut BodyRef, &'static SnakeTextureIndex),
+    (Without<SnakeBodyPart>, Without<Dead>),
+>;
+
 pub fn grow_snake(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
+    mut occupancy: ResMut<Occupancy>,
     mut grow_snake: EventReader<GrowSnakeEvent>,
-    mut head_query: Query<(Entity, &mut BodyRef), (Without<SnakeBodyPart>, Without<Dead>)>,
-    mut tail_query: Query<(Entity, &Transform, &mut TextureAtlas), With<Tail>>,
+    mut head_query: LiveHeads,
+    tail_query: Query<&GridPos, With<Tail>>,
 ) {
-    //todo fix unwrap bug
+    // the segments spawned here only exist once the commands apply, so all the growth of a snake
+    // this tick is added at once, from the tail it has now
+    let mut growth: Vec<(Entity, usize)> = Vec::new();
     for ev in grow_snake.read() {
-        let head_entity = ev.0;
-        let (_, mut body_ref) = head_query.get_mut(head_entity).unwrap();
-
-        let snake_texture = asset_server.load("textures/chars/sneks.png");
-        let snake_layout = TextureAtlasLayout::from_grid(UVec2::new(16, 16), 3, 5, None, None);
-        let snake_atlas_layout = texture_atlases.add(snake_layout);
-
-        // todo unwrap unwrap unwrap
-        let (old_tail, transform, mut old_atlas) =
-            tail_query.get_mut(*body_ref.0.last().unwrap()).unwrap();
-        let new_tail = spawn_body_part(
-            &head_entity,
-            &mut commands,
-            &snake_atlas_layout,
-            // 3, TODO need to make more textures
-            old_atlas.index,
-            &snake_texture,
-            transform,
-        );
-        commands.entity(old_tail).remove::<Tail>();
+        match growth.iter_mut().find(|(snake, _)| *snake == ev.snake) {
+            Some((_, segments)) => *segments += ev.segments,
+            None => growth.push((ev.snake, ev.segments)),
+        }
+    }
 
-        // set old tail to body part
-        old_atlas.layout = snake_atlas_layout.clone();
-        // old_atlas.index = 4;
+    for (head_entity, segments) in growth {
+        let Ok((mut body_ref, texture_index)) = head_query.get_mut(head_entity) else {
+            continue;
+        };
+        let Some(old_tail) = body_ref.0.last().copied() else {
+            continue;
+        };
+        let Ok(tail_pos) = tail_query.get(old_tail) else {
+            continue;
+        };
+        if segments == 0 {
+            continue;
+        }
 
-        commands.entity(new_tail).insert(Tail);
-        // add new tail to body ref of head
-        body_ref.0.push(new_tail);
+        // the new segments share the old tail's cell until the snake moves on
+        for _ in 0..segments {
+            let new_part = spawn_body_part(&mut commands, head_entity, *texture_index, *tail_pos);
+            // add new part to body ref of head
+            body_ref.0.push(new_part);
+        }
+        occupancy.set(tail_pos.0, Occupant::Body(head_entity));
+        commands.entity(old_tail).remove::<Tail>();
+        if let Some(new_tail) = body_ref.0.last() {
+            commands.entity(*new_tail).insert(Tail);
+        }
     }
 }
 
 fn spawn_body_part(
-    snake_head_ref: &Entity,
     commands: &mut Commands,
-    layout: &Handle<TextureAtlasLayout>,
-    index: usize,
-    texture: &Handle<Image>,
-    pos: &Transform,
+    snake_head: Entity,
+    texture_index: SnakeTextureIndex,
+    pos: GridPos,
 ) -> Entity {
     commands
         .spawn((
-            TextureAtlas {
-                layout: layout.clone(),
-                index,
-                ..Default::default()
-            },
-            SpriteBundle {
-                texture: texture.clone(),
-                transform: pos.clone(),
-                ..Default::default()
-            },
-            AnimationTarget,
             SnakeBodyPart,
             Collidible,
-            Bounding(TILE_SIZE / 2.),
-            SnakeHeadRef(Some(snake_head_ref.clone())),
+            pos,
+            texture_index,
+            SnakeHeadRef(Some(snake_head)),
             StateScoped(GameState::InGame),
         ))
         .id()
 }
 
 pub fn move_snakes(
+    grid: Res<Grid>,
+    level: Res<ActiveLevel>,
+    mut occupancy: ResMut<Occupancy>,
+    mut players: Query<(&mut DirectionQueue, &SnakeHeadRef), With<Player>>,
     mut head_query: Query<
-        (&mut Transform, &SnakeHead, &BodyRef),
+        (Entity, &mut GridPos, &mut SnakeHead, &BodyRef),
         (Without<SnakeBodyPart>, Without<Dead>),
     >,
-    mut snake_body_parts: Query<&mut Transform, With<SnakeBodyPart>>,
+    mut snake_body_parts: Query<&mut GridPos, With<SnakeBodyPart>>,
 ) {
-    for (mut transform, head, body_ref) in head_query.iter_mut() {
-        let move_speed = TILE_SIZE;
-        let move_delta = match head.direction {
-            SnakeDirection::Left => Vec3::new(-move_speed, 0., 0.),
-            SnakeDirection::Right => Vec3::new(move_speed, 0., 0.),
-            SnakeDirection::Up => Vec3::new(0., move_speed, 0.),
-            SnakeDirection::Down => Vec3::new(0., -move_speed, 0.),
+    // take at most one buffered turn per snake each tick
+    for (mut queue, head_ref) in players.iter_mut() {
+        let Some(Ok((_, _, mut head, _))) = head_ref.0.map(|head| head_query.get_mut(head)) else {
+            continue;
         };
+        if let Some(direction) = queue.pop() {
+            if direction != head.direction.opposite() {
+                head.direction = direction;
+            }
+        }
+    }
 
-        let mut prev_pos = transform.translation;
-        transform.translation += move_delta;
+    for (entity, mut head_pos, head, body_ref) in head_query.iter_mut() {
+        let old_head = head_pos.0;
+        let mut prev_pos = old_head;
+        let mut tail_cell = None;
+        head_pos.0 = grid.step(head_pos.0, &head.direction);
+        if let Some(exit) = level.portals.get(&head_pos.0) {
+            head_pos.0 = *exit;
+        }
 
-        let len = body_ref.0.iter().len();
-        for (i, body_entity) in body_ref.0.iter().enumerate() {
-            let res = snake_body_parts.get_mut(*body_entity);
+        for body_entity in body_ref.0.iter() {
+            let Ok(mut part_pos) = snake_body_parts.get_mut(*body_entity) else {
+                warn!("body part {body_entity:?} of {entity:?} not found");
+                continue;
+            };
 
-            match res {
-                Ok(_) => {}
-                Err(_) => {
-                    println!("body part not found");
-                    continue;
-                }
-            }
-            let mut part_transform = res.unwrap();
-
-            let old = part_transform.translation;
-            part_transform.translation = prev_pos;
-            if i + 1 == len {
-                // if prev above
-                if prev_pos.y > old.y {
-                    part_transform.rotation = Quat::from_rotation_z(90.0f32.to_radians());
-                } else if prev_pos.y < old.y {
-                    part_transform.rotation = Quat::from_rotation_z(-90.0f32.to_radians());
-                } else if prev_pos.x > old.x {
-                    part_transform.rotation = Quat::from_rotation_z(0.0f32.to_radians());
-                } else if prev_pos.x < old.x {
-                    part_transform.rotation = Quat::from_rotation_z(180.0f32.to_radians());
-                }
-            }
-            prev_pos = old;
+            std::mem::swap(&mut part_pos.0, &mut prev_pos);
+            tail_cell = Some(part_pos.0);
         }
 
-        // rotate head
-        match head.direction {
-            SnakeDirection::Left => {
-                transform.rotation = Quat::from_rotation_z(-180.0f32.to_radians())
-            }
-            SnakeDirection::Right => {
-                transform.rotation = Quat::from_rotation_z(0.0f32.to_radians())
-            }
-            SnakeDirection::Up => transform.rotation = Quat::from_rotation_z(90.0f32.to_radians()),
-            SnakeDirection::Down => {
-                transform.rotation = Quat::from_rotation_z(-90.0f32.to_radians())
+        // The new head cell is claimed in check_death_collision once every snake has moved,
+        // so a head may follow any tail into the cell it vacated this tick.
+        match tail_cell {
+            None => occupancy.clear_if(old_head, Occupant::Head(entity)),
+            Some(tail_cell) => {
+                occupancy.set(old_head, Occupant::Body(entity));
+                // freshly grown tails share their cell until the snake has moved once
+                if prev_pos != tail_cell {
+                    occupancy.clear_if(prev_pos, Occupant::Body(entity));
+                }
             }
         }
     }
 }
 
-pub fn check_death_collision(
-    mut commands: Commands,
-    mut head_query: Query<(Entity, &Transform, &SnakeHead, &Bounding), Without<Dead>>,
-    mut collidibles: Query<(&Transform, &Bounding), With<Collidible>>,
-    mut next_state: ResMut<NextState<GamePhase>>,
+fn direction_angle(direction: &SnakeDirection) -> f32 {
+    match direction {
+        SnakeDirection::Left => 180.0f32.to_radians(),
+        SnakeDirection::Right => 0.0f32.to_radians(),
+        SnakeDirection::Up => 90.0f32.to_radians(),
+        SnakeDirection::Down => -90.0f32.to_radians(),
+    }
+}
+
+/**
+ * Rotates the head sprite to face its direction and the tail sprite to face the part ahead of it.
+ */
+pub fn orient_snakes(
+    grid: Res<Grid>,
+    mut head_query: Query<(&mut Transform, &SnakeHead, &BodyRef), Without<SnakeBodyPart>>,
+    mut snake_body_parts: Query<(&GridPos, &mut Transform), With<SnakeBodyPart>>,
 ) {
-    for (entity, head_transform, _, head_size) in head_query.iter_mut() {
-        let head_pos = head_transform.translation;
-
-        for (collidable_transform, collidable_size) in collidibles.iter() {
-            if circles_touching(
-                head_transform,
-                head_size,
-                collidable_transform,
-                collidable_size,
-            ) {
-                commands.entity(entity).insert(Dead);
-                // next_state.set(GamePhase::Dead);
-            }
-        }
+    for (mut transform, head, body_ref) in head_query.iter_mut() {
+        transform.rotation = Quat::from_rotation_z(direction_angle(&head.direction));
 
-        if head_pos.x < -(WORLD_SIZE_X as f32 / 2. as f32 * TILE_SIZE)
-            || head_pos.x > WORLD_SIZE_X as f32 / 2. as f32 * TILE_SIZE
-            || head_pos.y < -(WORLD_SIZE_Y as f32 / 2. as f32 * TILE_SIZE)
-            || head_pos.y > WORLD_SIZE_Y as f32 / 2. as f32 * TILE_SIZE
-        {
-            commands.entity(entity).insert(Dead);
+        let len = body_ref.0.len();
+        if len < 2 {
+            continue;
+        }
+        let Ok([(ahead, _), (tail, mut tail_transform)]) =
+            snake_body_parts.get_many_mut([body_ref.0[len - 2], body_ref.0[len - 1]])
+        else {
+            continue;
+        };
+        if let Some(direction) = SnakeDirection::from_delta(grid.offset(tail.0, ahead.0)) {
+            tail_transform.rotation = Quat::from_rotation_z(direction_angle(&direction));
         }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn check_apple_collision(
     mut commands: Commands,
-    mut head_query: Query<
-        (Entity, &Transform, &SnakeHead, &Bounding),
-        (Without<SnakeBodyPart>, Without<Dead>),
-    >,
-    apple_query: Query<(Entity, &Transform, &Bounding), With<Apple>>,
+    occupancy: Res<Occupancy>,
+    rules: Res<GameRules>,
+    head_query: Query<Entity, (With<SnakeHead>, Without<Dead>)>,
+    apple_query: Query<(Entity, &GridPos, Has<BonusApple>), With<Apple>>,
     mut spawn_apple: EventWriter<MoveAppleEvent>,
     mut grow_snake: EventWriter<GrowSnakeEvent>,
     mut fixed_time: ResMut<Time<Fixed>>,
-    mut player_query: Query<(&mut Score, &SnakeHeadRef), With<Player>>,
+    mut player_query: Query<(&mut Score, &SnakeHeadRef, Option<&mut PlayerStats>), With<Player>>,
 ) {
-    for (mut score, headRef) in player_query.iter_mut() {
-        //todo fixup this
-        match headRef.0 {
-            None => continue,
-            _ => {}
-        }
+    for (mut score, head_ref, mut stats) in player_query.iter_mut() {
+        let Some(Ok(entity)) = head_ref.0.map(|head| head_query.get(head)) else {
+            continue;
+        };
 
-        let head = head_query.get(headRef.0.unwrap());
-        match head {
-            Ok(_) => {}
-            Err(_) => continue,
-        }
-        let (entity, head_transform, _, head_size) = head.unwrap();
-        for (apple_entity, apple_transform, apple_size) in apple_query.iter() {
-            if circles_touching(apple_transform, apple_size, head_transform, head_size) {
+        for (apple, apple_pos, bonus) in apple_query.iter() {
+            if occupancy.get(apple_pos.0) == Some(Occupant::Head(entity)) {
                 // EATEN
-                spawn_apple.send(MoveAppleEvent);
-                grow_snake.send(GrowSnakeEvent(entity));
+                if bonus {
+                    // the head has already taken its cell
+                    commands.entity(apple).despawn_recursive();
+                } else {
+                    spawn_apple.send(MoveAppleEvent);
+                }
+                grow_snake.send(GrowSnakeEvent {
+                    snake: entity,
+                    segments: rules.growth_per_apple as usize,
+                });
 
                 score.value += 1;
+                if let Some(stats) = stats.as_mut() {
+                    stats.apples += 1;
+                }
 
-                let new_timestep = fixed_time.timestep().mul_f32(0.95);
+                let new_timestep = rules.sped_up(fixed_time.timestep());
                 fixed_time.set_timestep(new_timestep);
             }
         }
     }
-    // for (entity, head_transform, _, head_size) in head_query.iter_mut() {
-    // }
+}
+
+/**
+ * Makes a snake that lost part of its body blink for a moment.
+ */
+pub fn flick_on_collision(mut commands: Commands, mut collisions: EventReader<CollisionEvent>) {
+    for ev in collisions.read() {
+        debug!("{:?} at {} resolved as {:?}", ev.kind, ev.cell, ev.outcome);
+
+        let bitten = match (ev.kind, ev.outcome) {
+            (
+                CollisionKind::BodyBite { victim },
+                CollisionOutcome::Cut { .. } | CollisionOutcome::Stolen { .. },
+            ) => victim,
+            (CollisionKind::SelfBite, CollisionOutcome::Cut { .. }) => ev.biter,
+            _ => continue,
+        };
+        if let Some(mut entity) = commands.get_entity(bitten) {
+            entity.insert(Flick {
+                switch_timer: Timer::from_seconds(0.1, TimerMode::Repeating),
+                duration: Timer::from_seconds(1., TimerMode::Once),
+            });
+        }
+    }
 }
 
 pub fn example_update(
@@ -559,76 +621,44 @@ pub fn example_update(
     }
 }
 
+/**
+ * Ends the match once every snake is dead or there is no room left for another apple.
+ */
 pub fn check_all_dead(
     head_query: Query<(Entity, Has<Dead>), With<SnakeHead>>,
+    board_full: Res<BoardFull>,
     mut next_state: ResMut<NextState<GamePhase>>,
 ) {
     let all_dead = head_query.iter().all(|(_, dead)| dead);
 
-    if all_dead {
+    if all_dead || board_full.0 {
         next_state.set(GamePhase::Dead);
     }
 }
 
-pub fn dead_controls(
-    keyboard: Res<ButtonInput<KeyCode>>,
-    mut next_state: ResMut<NextState<GameState>>,
-) {
-    if keyboard.just_pressed(KeyCode::Space) {
-        println!("Setting next state to EnterGame");
-        next_state.set(GameState::EnterGame);
-    }
-}
-
-pub fn dead_text(mut commands: Commands, asset_server: Res<AssetServer>) {
-    commands
-        .spawn((
-            NodeBundle {
-                style: Style {
-                    width: Val::Percent(100.),
-                    height: Val::Percent(100.),
-                    display: Display::Flex,
-                    flex_direction: FlexDirection::Column,
-                    ..default()
-                },
+/**
+ * Shows the match seed in a corner of the screen when running with `--debug`.
+ */
+pub fn debug_overlay(mut commands: Commands, seed: Res<Seed>, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(8.),
+                bottom: Val::Px(8.),
                 ..default()
             },
-            StateScoped(GameState::InGame),
-        ))
-        .with_children(|builder| {
-            builder
-                .spawn(NodeBundle {
-                    style: Style {
-                        width: Val::Percent(100.),
-                        height: Val::Percent(40.),
-                        display: Display::Flex,
-                        justify_content: JustifyContent::Center,
-                        align_items: AlignItems::Center,
-                        ..default()
-                    },
-                    ..default()
-                })
-                // .insert(
-                //     NineSliceUiTexture::from_slice(
-                //         server.load("panel_atlas.png"),
-                //         Rect::new(0., 0., 32., 32.),
-                //     )
-                //     .with_blend_color(Color::from(LinearRgba::RED))
-                //     .with_blend_mix(0.5),
-                // )
-                .with_children(|builder| {
-                    builder.spawn((TextBundle {
-                        text: Text::from_section(
-                            format!("You crashed!"),
-                            TextStyle {
-                                font_size: 30.,
-                                color: Color::WHITE,
-                                font: asset_server.load("fonts/visitor.ttf"),
-                                ..default()
-                            },
-                        ),
-                        ..default()
-                    },));
-                });
-        });
+            text: Text::from_section(
+                format!("seed {}", seed.0),
+                TextStyle {
+                    font_size: 20.,
+                    color: Color::WHITE,
+                    font: asset_server.load("fonts/visitor.ttf"),
+                },
+            ),
+            ..default()
+        },
+        DebugText,
+        StateScoped(GameState::InGame),
+    ));
 }
@@ -0,0 +1,199 @@
+use bevy::{input::InputSystem, prelude::*, utils::HashMap};
+
+use crate::{
+    game::{
+        components::SnakeHead,
+        prelude::{DirectionQueue, SnakeDirection, SnakeHeadRef},
+    },
+    GamePhase,
+};
+
+/// Stick positions closer to the center than this are ignored.
+pub const STICK_DEADZONE: f32 = 0.5;
+
+/// The direction a D-pad button stands for.
+pub fn dpad_direction(button: GamepadButtonType) -> Option<SnakeDirection> {
+    match button {
+        GamepadButtonType::DPadUp => Some(SnakeDirection::Up),
+        GamepadButtonType::DPadDown => Some(SnakeDirection::Down),
+        GamepadButtonType::DPadLeft => Some(SnakeDirection::Left),
+        GamepadButtonType::DPadRight => Some(SnakeDirection::Right),
+        _ => None,
+    }
+}
+
+/// Snaps a stick position to the closest of the four directions, `None` inside the deadzone.
+pub fn stick_direction(stick: Vec2) -> Option<SnakeDirection> {
+    if stick.length() < STICK_DEADZONE {
+        return None;
+    }
+    if stick.x.abs() > stick.y.abs() {
+        Some(if stick.x > 0. {
+            SnakeDirection::Right
+        } else {
+            SnakeDirection::Left
+        })
+    } else {
+        Some(if stick.y > 0. {
+            SnakeDirection::Up
+        } else {
+            SnakeDirection::Down
+        })
+    }
+}
+
+/**
+ * The directions every gamepad was pushed in this frame, from the D-pad or from the left stick
+ * moving into a new direction. Holding a direction only counts once.
+ */
+#[derive(Resource, Default)]
+pub struct GamepadDirections {
+    stick: HashMap<Gamepad, SnakeDirection>,
+    pub just_pressed: Vec<(Gamepad, SnakeDirection)>,
+}
+
+impl GamepadDirections {
+    pub fn for_gamepad(&self, gamepad: Gamepad) -> impl Iterator<Item = SnakeDirection> + '_ {
+        self.just_pressed
+            .iter()
+            .filter(move |(pressed_on, _)| *pressed_on == gamepad)
+            .map(|(_, direction)| direction.clone())
+    }
+}
+
+fn read_gamepad_directions(
+    gamepads: Res<Gamepads>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    mut directions: ResMut<GamepadDirections>,
+) {
+    let directions = &mut *directions;
+    directions.just_pressed.clear();
+    directions
+        .stick
+        .retain(|gamepad, _| gamepads.contains(*gamepad));
+
+    for button in buttons.get_just_pressed() {
+        if let Some(direction) = dpad_direction(button.button_type) {
+            directions.just_pressed.push((button.gamepad, direction));
+        }
+    }
+
+    for gamepad in gamepads.iter() {
+        let axis = |axis_type| axes.get(GamepadAxis::new(gamepad, axis_type)).unwrap_or(0.);
+        let stick = Vec2::new(
+            axis(GamepadAxisType::LeftStickX),
+            axis(GamepadAxisType::LeftStickY),
+        );
+        match stick_direction(stick) {
+            Some(direction) if directions.stick.get(&gamepad) != Some(&direction) => {
+                directions.stick.insert(gamepad, direction.clone());
+                directions.just_pressed.push((gamepad, direction));
+            }
+            Some(_) => {}
+            None => {
+                directions.stick.remove(&gamepad);
+            }
+        }
+    }
+}
+
+/// Whether any gamepad's button of the given type was pressed this frame.
+pub fn any_gamepad_just_pressed(
+    buttons: &ButtonInput<GamepadButton>,
+    button_type: GamepadButtonType,
+) -> bool {
+    buttons
+        .get_just_pressed()
+        .any(|button| button.button_type == button_type)
+}
+
+/// Steers a `Player`'s snake with a gamepad, next to or instead of its `ControlScheme`.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct GamepadControls(pub Gamepad);
+
+fn gamepad_steering(
+    directions: Res<GamepadDirections>,
+    mut players: Query<(&GamepadControls, &mut DirectionQueue, &SnakeHeadRef)>,
+    snake_heads: Query<&SnakeHead>,
+) {
+    for (controls, mut queue, snake_head_ref) in players.iter_mut() {
+        let Some(Ok(snake_head)) = snake_head_ref.0.map(|head| snake_heads.get(head)) else {
+            continue;
+        };
+        for direction in directions.for_gamepad(controls.0) {
+            queue.push(direction, &snake_head.direction);
+        }
+    }
+}
+
+/**
+ * Controllers as a source of turns: the D-pad and the left stick steer, Start pauses.
+ */
+pub struct GamepadPlugin;
+impl Plugin for GamepadPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GamepadDirections>()
+            .add_systems(PreUpdate, read_gamepad_directions.after(InputSystem))
+            .add_systems(
+                Update,
+                gamepad_steering.run_if(in_state(GamePhase::Playing)),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dpad_buttons_map_to_directions() {
+        assert_eq!(
+            dpad_direction(GamepadButtonType::DPadUp),
+            Some(SnakeDirection::Up)
+        );
+        assert_eq!(
+            dpad_direction(GamepadButtonType::DPadDown),
+            Some(SnakeDirection::Down)
+        );
+        assert_eq!(
+            dpad_direction(GamepadButtonType::DPadLeft),
+            Some(SnakeDirection::Left)
+        );
+        assert_eq!(
+            dpad_direction(GamepadButtonType::DPadRight),
+            Some(SnakeDirection::Right)
+        );
+        assert_eq!(dpad_direction(GamepadButtonType::South), None);
+    }
+
+    #[test]
+    fn stick_ignores_the_deadzone() {
+        assert_eq!(stick_direction(Vec2::ZERO), None);
+        assert_eq!(stick_direction(Vec2::new(0.3, -0.3)), None);
+        assert_eq!(
+            stick_direction(Vec2::new(STICK_DEADZONE, 0.)),
+            Some(SnakeDirection::Right)
+        );
+    }
+
+    #[test]
+    fn stick_snaps_to_the_dominant_axis() {
+        assert_eq!(
+            stick_direction(Vec2::new(0.9, 0.4)),
+            Some(SnakeDirection::Right)
+        );
+        assert_eq!(
+            stick_direction(Vec2::new(-0.9, 0.4)),
+            Some(SnakeDirection::Left)
+        );
+        assert_eq!(
+            stick_direction(Vec2::new(0.4, 0.9)),
+            Some(SnakeDirection::Up)
+        );
+        assert_eq!(
+            stick_direction(Vec2::new(0.4, -0.9)),
+            Some(SnakeDirection::Down)
+        );
+    }
+}
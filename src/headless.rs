@@ -0,0 +1,237 @@
+use std::{fmt, process, thread, time::Duration};
+
+use bevy::{prelude::*, state::app::StatesPlugin, time::TimeUpdateStrategy, utils::HashMap};
+use bevy_asset_loader::prelude::{ConfigureLoadingState, LoadingState, LoadingStateAppExt};
+use bevy_turborand::prelude::RngPlugin;
+
+use crate::{
+    add_game_states,
+    config::{ProgramConfig, Seed},
+    game::{
+        ai::{AiController, AiSlots},
+        brain::BrainRegistry,
+        components::Dead,
+        grid::Grid,
+        level::{LevelAssets, SelectedLevel},
+        prelude::{
+            BodyRef, ControlScheme, DirectionQueue, Named, Player, PlayerBundle, Score,
+            SnakeHeadRef, SnakeTextureIndex,
+        },
+        rules::RulesAssets,
+        stats::{match_winner, PlayerStats},
+        SimulationPlugin,
+    },
+    GamePhase, GameState,
+};
+
+/// Give up if the level and rules assets take longer than this to load.
+const LOAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The level and rules assets didn't finish loading within `LOAD_TIMEOUT`.
+#[derive(Debug)]
+pub struct LoadTimeout;
+
+impl fmt::Display for LoadTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "timed out loading assets")
+    }
+}
+
+impl std::error::Error for LoadTimeout {}
+
+#[derive(Resource)]
+struct PlayerCount(usize);
+
+/// The order a player was spawned in, so results come out in slot order.
+#[derive(Component)]
+struct Slot(usize);
+
+fn spawn_players(
+    mut commands: Commands,
+    player_count: Res<PlayerCount>,
+    ai_slots: Res<AiSlots>,
+    registry: Res<BrainRegistry>,
+) {
+    for i in 0..player_count.0 {
+        let mut player = commands.spawn((
+            PlayerBundle {
+                player: Player,
+                name: Named(format!("Player {}", i + 1)),
+                control_scheme: ControlScheme {
+                    directional_controls: HashMap::new(),
+                },
+                direction_queue: DirectionQueue::default(),
+                snake_head_ref: SnakeHeadRef(None),
+                score: Score { value: 0 },
+                texture_index: SnakeTextureIndex(i % 4),
+            },
+            Slot(i),
+        ));
+        if let Some(controller) = ai_slots.controller(i, &registry) {
+            player.insert(controller);
+        }
+    }
+}
+
+pub struct PlayerResult {
+    pub name: String,
+    /// The bot that played, or `human` for a snake nobody steered.
+    pub controller: String,
+    pub score: u32,
+    pub length: usize,
+    pub dead: bool,
+    pub stats: PlayerStats,
+}
+
+pub struct MatchResult {
+    pub seed: u64,
+    pub ticks: u32,
+    /// In slot order.
+    pub players: Vec<PlayerResult>,
+}
+
+impl MatchResult {
+    /// The slot of the winner, decided the same way as on the results screen.
+    pub fn winner(&self) -> Option<usize> {
+        match_winner(
+            self.players
+                .iter()
+                .enumerate()
+                .map(|(slot, player)| (slot, &player.stats, player.score)),
+        )
+    }
+}
+
+fn collect_result(world: &mut World, seed: u64, ticks: u32) -> MatchResult {
+    let mut heads = world.query::<(&BodyRef, Has<Dead>)>();
+    let mut players = world.query_filtered::<(
+        &Slot,
+        &Named,
+        &Score,
+        &SnakeHeadRef,
+        Option<&AiController>,
+        Option<&PlayerStats>,
+    ), With<Player>>();
+
+    let mut results: Vec<(usize, PlayerResult)> = players
+        .iter(world)
+        .map(|(slot, name, score, head_ref, ai, stats)| {
+            let (length, dead) = head_ref
+                .0
+                .and_then(|head| heads.get(world, head).ok())
+                .map_or((0, true), |(body_ref, dead)| (body_ref.0.len() + 1, dead));
+            let result = PlayerResult {
+                name: name.0.clone(),
+                controller: ai.map_or("human".to_string(), |ai| ai.name.clone()),
+                score: score.value,
+                length,
+                dead,
+                stats: stats.cloned().unwrap_or_default(),
+            };
+            (slot.0, result)
+        })
+        .collect();
+    results.sort_by_key(|(slot, _)| *slot);
+
+    MatchResult {
+        seed,
+        ticks,
+        players: results.into_iter().map(|(_, result)| result).collect(),
+    }
+}
+
+/**
+ * Plays one match without a window: loads the level and rules, then steps the fixed tick
+ * until every snake is dead or `cfg.ticks` ticks have passed. Gives up if the assets don't
+ * load in time.
+ */
+pub fn play_match(
+    cfg: &ProgramConfig,
+    seed: u64,
+    players: usize,
+    ai: &AiSlots,
+) -> Result<MatchResult, LoadTimeout> {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        AssetPlugin::default(),
+        StatesPlugin,
+        RngPlugin::new().with_rng_seed(seed),
+    ));
+    add_game_states(&mut app)
+        .add_loading_state(
+            LoadingState::new(GameState::AssetLoading)
+                .continue_to_state(GameState::InGame)
+                .load_collection::<LevelAssets>()
+                .load_collection::<RulesAssets>(),
+        )
+        .add_plugins(SimulationPlugin)
+        .insert_resource(Grid {
+            mode: cfg.arena_mode,
+            ..default()
+        })
+        .insert_resource(SelectedLevel(cfg.level))
+        .insert_resource(Seed(seed))
+        .insert_resource(PlayerCount(players))
+        .insert_resource(ai.clone())
+        .insert_resource(cfg.brain_registry())
+        // time only moves when a tick is stepped below
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::ZERO))
+        .add_systems(OnExit(GameState::AssetLoading), spawn_players);
+
+    app.finish();
+    app.cleanup();
+    app.world_mut()
+        .resource_mut::<Time<Virtual>>()
+        .set_max_delta(Duration::from_secs(60));
+
+    let started = std::time::Instant::now();
+    while *app.world().resource::<State<GameState>>().get() != GameState::InGame {
+        if started.elapsed() > LOAD_TIMEOUT {
+            return Err(LoadTimeout);
+        }
+        app.update();
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    let mut ticks = 0;
+    while ticks < cfg.ticks {
+        // advance time by exactly one tick, whatever the current speed
+        let timestep = app.world().resource::<Time<Fixed>>().timestep();
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(timestep));
+        app.update();
+        ticks += 1;
+
+        let phase = app.world().get_resource::<State<GamePhase>>();
+        if phase.is_some_and(|phase| *phase.get() == GamePhase::Dead) {
+            break;
+        }
+    }
+
+    Ok(collect_result(app.world_mut(), seed, ticks))
+}
+
+/**
+ * Runs a single match headless and prints the result.
+ */
+pub fn run(cfg: &ProgramConfig) {
+    let result = play_match(cfg, cfg.seed, cfg.players, &cfg.ai).unwrap_or_else(|err| {
+        println!("Could not play the match: {err}");
+        process::exit(1);
+    });
+
+    println!(
+        "Match over after {} ticks (seed {})",
+        result.ticks, result.seed
+    );
+    for player in result.players.iter() {
+        println!(
+            "{} ({}): score {}, length {}, {}",
+            player.name,
+            player.controller,
+            player.score,
+            player.length,
+            if player.dead { "crashed" } else { "alive" }
+        );
+    }
+}
@@ -0,0 +1,692 @@
+use std::{
+    collections::VecDeque,
+    fs, io,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{
+    input::keyboard::{Key, KeyboardInput},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    controls::CONFIG_DIR,
+    game::{
+        ai::AiController,
+        components::ElapsedTicks,
+        grid::{ArenaMode, Grid},
+        level::ActiveLevel,
+        prelude::{BodyRef, Named, Player, Score, SnakeHeadRef},
+        replay::Playback,
+        rounds::VersusMatch,
+    },
+    menu::{MenuAction, MenuInput, MENU_SELECTED_COLOR},
+    GamePhase, GameState,
+};
+
+/// How many results are kept for every arena mode and arena.
+pub const TABLE_SIZE: usize = 10;
+const HIGH_SCORES_FILE: &str = "high_scores.json";
+const MAX_NAME_LENGTH: usize = 12;
+const ARENA_MODES: [ArenaMode; 2] = [ArenaMode::Walled, ArenaMode::Wrap];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HighScore {
+    pub name: String,
+    pub score: u32,
+    pub length: usize,
+    /// How many ticks the match lasted, which doesn't depend on how fast they went by.
+    #[serde(default)]
+    pub ticks: u32,
+    pub mode: ArenaMode,
+    /// The name of the level played.
+    pub arena: String,
+    /// When the score was set, in seconds since the Unix epoch.
+    pub date: u64,
+}
+
+/**
+ * The best results of every arena mode and arena, kept in `high_scores.json` in the user's data
+ * directory. Entries are kept best first, and the older of two equal scores ranks higher.
+ */
+#[derive(Resource, Debug, Default, Serialize, Deserialize)]
+pub struct HighScores {
+    entries: Vec<HighScore>,
+}
+
+impl HighScores {
+    fn path() -> Option<PathBuf> {
+        Some(dirs::data_dir()?.join(CONFIG_DIR).join(HIGH_SCORES_FILE))
+    }
+
+    /// The saved high scores, or none if there are none or they can't be read.
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return HighScores::default();
+        };
+        let high_scores = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).map_err(io::Error::from),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(HighScores::default()),
+            Err(err) => Err(err),
+        };
+        high_scores.unwrap_or_else(|err| {
+            warn!("could not load high scores from {}: {err}", path.display());
+            HighScores::default()
+        })
+    }
+
+    pub fn save(&self) -> io::Result<PathBuf> {
+        let path = Self::path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no data directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+
+    /// The best results, optionally only those of one arena mode and one arena.
+    pub fn filtered<'a>(
+        &'a self,
+        mode: Option<ArenaMode>,
+        arena: Option<&'a str>,
+    ) -> impl Iterator<Item = &'a HighScore> + 'a {
+        self.entries.iter().filter(move |entry| {
+            (mode.is_none() || mode == Some(entry.mode))
+                && (arena.is_none() || arena == Some(entry.arena.as_str()))
+        })
+    }
+
+    /// Whether `score` would make it into the table of its arena mode and arena.
+    pub fn qualifies(&self, mode: ArenaMode, arena: &str, score: u32) -> bool {
+        let table: Vec<&HighScore> = self.filtered(Some(mode), Some(arena)).collect();
+        score > 0 && (table.len() < TABLE_SIZE || table.iter().any(|entry| entry.score < score))
+    }
+
+    pub fn insert(&mut self, high_score: HighScore) {
+        let at = self
+            .entries
+            .iter()
+            .position(|entry| entry.score < high_score.score)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(at, high_score);
+
+        // drop whatever fell off the bottom of its table
+        let mut kept: Vec<(ArenaMode, String, usize)> = Vec::new();
+        self.entries.retain(|entry| {
+            match kept
+                .iter_mut()
+                .find(|(mode, arena, _)| *mode == entry.mode && *arena == entry.arena)
+            {
+                Some((_, _, count)) => {
+                    *count += 1;
+                    *count <= TABLE_SIZE
+                }
+                None => {
+                    kept.push((entry.mode, entry.arena.clone(), 1));
+                    true
+                }
+            }
+        });
+    }
+
+    /// The arenas there are scores for, in alphabetical order.
+    pub fn arenas(&self) -> Vec<String> {
+        let mut arenas: Vec<String> = self
+            .entries
+            .iter()
+            .map(|entry| entry.arena.clone())
+            .collect();
+        arenas.sort();
+        arenas.dedup();
+        arenas
+    }
+}
+
+fn mode_label(mode: ArenaMode) -> &'static str {
+    match mode {
+        ArenaMode::Walled => "WALLED",
+        ArenaMode::Wrap => "WRAP AROUND",
+    }
+}
+
+/// `YYYY-MM-DD` for a time in seconds since the Unix epoch, in UTC.
+fn date_label(date: u64) -> String {
+    // days to a civil date, after Howard Hinnant's `civil_from_days`
+    let days = (date / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/**
+ * The new records of the match that just ended, waiting for their players to type a name. The
+ * first one is being entered.
+ */
+#[derive(Resource)]
+pub struct PendingRecords {
+    records: VecDeque<HighScore>,
+    name: String,
+}
+
+#[derive(Component)]
+struct RecordPrompt;
+
+#[derive(Component)]
+struct RecordPromptText;
+
+type HumanPlayers<'w, 's> = Query<
+    'w,
+    's,
+    (&'static Named, &'static Score, &'static SnakeHeadRef),
+    (With<Player>, Without<AiController>),
+>;
+
+/**
+ * Looks for new records among the human players once every snake is dead. Bots, replays and
+ * versus matches don't set records, the latter adding up scores over several rounds.
+ */
+fn check_for_records(
+    mut commands: Commands,
+    high_scores: Res<HighScores>,
+    grid: Res<Grid>,
+    level: Res<ActiveLevel>,
+    ticks: Res<ElapsedTicks>,
+    players: HumanPlayers,
+    heads: Query<&BodyRef>,
+) {
+    let date = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+
+    let mut records: Vec<HighScore> = players
+        .iter()
+        .filter(|(_, score, _)| high_scores.qualifies(grid.mode, &level.name, score.value))
+        .map(|(name, score, head_ref)| HighScore {
+            name: name.0.clone(),
+            score: score.value,
+            length: head_ref
+                .0
+                .and_then(|head| heads.get(head).ok())
+                .map_or(0, |body_ref| body_ref.0.len() + 1),
+            ticks: ticks.0,
+            mode: grid.mode,
+            arena: level.name.clone(),
+            date,
+        })
+        .collect();
+    if records.is_empty() {
+        return;
+    }
+    records.sort_by_key(|record| std::cmp::Reverse(record.score));
+
+    commands.insert_resource(PendingRecords {
+        records: records.into(),
+        name: String::new(),
+    });
+}
+
+fn spawn_record_prompt(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/visitor.ttf");
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::BLACK.with_alpha(0.8).into(),
+                z_index: ZIndex::Global(8),
+                ..default()
+            },
+            RecordPrompt,
+            StateScoped(GameState::InGame),
+        ))
+        .with_children(|builder| {
+            builder.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 40.,
+                        color: Color::WHITE,
+                        font,
+                    },
+                )
+                .with_text_justify(JustifyText::Center),
+                RecordPromptText,
+            ));
+        });
+}
+
+/**
+ * Types the name for the first pending record. Enter or the south button saves it under that
+ * name, or under the player's name when none was typed. Escape always uses the player's name.
+ */
+fn record_name_entry(
+    mut commands: Commands,
+    mut typed: EventReader<KeyboardInput>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+    mut pending: ResMut<PendingRecords>,
+    mut high_scores: ResMut<HighScores>,
+    prompts: Query<Entity, With<RecordPrompt>>,
+) {
+    // the keys that were steering the snakes a moment ago aren't part of the name
+    if pending.is_added() {
+        typed.clear();
+        return;
+    }
+    let mut done = buttons
+        .get_just_pressed()
+        .any(|button| button.button_type == GamepadButtonType::South);
+    for event in typed.read() {
+        if done || !event.state.is_pressed() {
+            continue;
+        }
+        let name = &mut pending.name;
+        match &event.logical_key {
+            Key::Character(text) => {
+                for c in text.chars().filter(|c| !c.is_control()) {
+                    if name.chars().count() < MAX_NAME_LENGTH {
+                        name.push(c);
+                    }
+                }
+            }
+            Key::Space if name.chars().count() < MAX_NAME_LENGTH => name.push(' '),
+            Key::Backspace => {
+                name.pop();
+            }
+            Key::Enter => done = true,
+            Key::Escape => {
+                name.clear();
+                done = true;
+            }
+            _ => {}
+        }
+    }
+    if !done {
+        return;
+    }
+
+    let pending = &mut *pending;
+    let Some(mut record) = pending.records.pop_front() else {
+        return;
+    };
+    if !pending.name.trim().is_empty() {
+        record.name = pending.name.trim().to_string();
+    }
+    high_scores.insert(record);
+    match high_scores.save() {
+        Ok(path) => info!("high scores saved to {}", path.display()),
+        Err(err) => warn!("could not save high scores: {err}"),
+    }
+
+    match pending.records.front() {
+        Some(_) => pending.name.clear(),
+        None => {
+            commands.remove_resource::<PendingRecords>();
+            for prompt in prompts.iter() {
+                commands.entity(prompt).despawn_recursive();
+            }
+        }
+    }
+}
+
+fn update_record_prompt(
+    pending: Res<PendingRecords>,
+    mut texts: Query<&mut Text, With<RecordPromptText>>,
+) {
+    let Some(record) = pending.records.front() else {
+        return;
+    };
+    for mut text in texts.iter_mut() {
+        text.sections[0].value = format!(
+            "NEW HIGH SCORE!\n{} POINTS FOR {}\n\nENTER YOUR NAME\n{}_",
+            record.score,
+            record.name.to_uppercase(),
+            pending.name
+        );
+    }
+}
+
+fn clear_pending_records(mut commands: Commands) {
+    commands.remove_resource::<PendingRecords>();
+}
+
+/// Whether the leaderboard is showing, on top of whatever else is.
+#[derive(States, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum HighScoreScreen {
+    #[default]
+    Closed,
+    Open,
+}
+
+/// Which scores the leaderboard shows, `None` for all of them.
+#[derive(Resource, Default)]
+struct LeaderboardFilter {
+    mode: Option<ArenaMode>,
+    arena: Option<String>,
+}
+
+#[derive(Component)]
+struct LeaderboardRows;
+
+#[derive(Component)]
+struct LeaderboardFilterText;
+
+/// Steps through `None` and then every option, wrapping around.
+fn cycle<T: Clone + PartialEq>(current: &Option<T>, options: &[T], step: isize) -> Option<T> {
+    let position = current
+        .as_ref()
+        .and_then(|current| options.iter().position(|option| option == current))
+        .map_or(0, |i| i + 1) as isize;
+    let count = options.len() as isize + 1;
+    match (position + step).rem_euclid(count) {
+        0 => None,
+        i => Some(options[i as usize - 1].clone()),
+    }
+}
+
+/**
+ * Left and right pick the arena mode to show, up and down the arena.
+ */
+fn leaderboard_input(
+    input: MenuInput,
+    high_scores: Res<HighScores>,
+    mut filter: ResMut<LeaderboardFilter>,
+    mut next_state: ResMut<NextState<HighScoreScreen>>,
+) {
+    for action in input.actions() {
+        match action {
+            MenuAction::Left => filter.mode = cycle(&filter.mode, &ARENA_MODES, -1),
+            MenuAction::Right => filter.mode = cycle(&filter.mode, &ARENA_MODES, 1),
+            MenuAction::Up => filter.arena = cycle(&filter.arena, &high_scores.arenas(), -1),
+            MenuAction::Down => filter.arena = cycle(&filter.arena, &high_scores.arenas(), 1),
+            MenuAction::Select | MenuAction::Back => next_state.set(HighScoreScreen::Closed),
+        }
+    }
+}
+
+fn spawn_leaderboard(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/visitor.ttf");
+    let text_style = |font_size: f32| TextStyle {
+        font_size,
+        color: Color::WHITE,
+        font: font.clone(),
+    };
+    commands.insert_resource(LeaderboardFilter::default());
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(16.),
+                    ..default()
+                },
+                background_color: Color::BLACK.with_alpha(0.9).into(),
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            StateScoped(HighScoreScreen::Open),
+        ))
+        .with_children(|builder| {
+            builder.spawn(TextBundle::from_section("HIGH SCORES", text_style(50.)));
+            builder.spawn((
+                TextBundle::from_section("", text_style(30.)),
+                LeaderboardFilterText,
+            ));
+            builder.spawn((
+                NodeBundle {
+                    style: Style {
+                        display: Display::Grid,
+                        grid_template_columns: vec![
+                            GridTrack::px(50.),
+                            GridTrack::px(200.),
+                            GridTrack::px(100.),
+                            GridTrack::px(110.),
+                            GridTrack::px(90.),
+                            GridTrack::px(180.),
+                            GridTrack::px(180.),
+                            GridTrack::px(170.),
+                        ],
+                        row_gap: Val::Px(6.),
+                        ..default()
+                    },
+                    ..default()
+                },
+                LeaderboardRows,
+            ));
+            builder.spawn(TextBundle::from_section(
+                "LEFT/RIGHT: MODE  UP/DOWN: ARENA  ESC: BACK",
+                text_style(20.),
+            ));
+        });
+}
+
+fn update_leaderboard(
+    mut commands: Commands,
+    high_scores: Res<HighScores>,
+    filter: Res<LeaderboardFilter>,
+    asset_server: Res<AssetServer>,
+    rows: Query<Entity, With<LeaderboardRows>>,
+    mut filter_texts: Query<&mut Text, With<LeaderboardFilterText>>,
+) {
+    if !filter.is_changed() && !high_scores.is_changed() {
+        return;
+    }
+    for mut text in filter_texts.iter_mut() {
+        text.sections[0].value = format!(
+            "< {} >  ^ {} v",
+            filter.mode.map_or("ALL MODES", mode_label),
+            filter
+                .arena
+                .as_deref()
+                .map_or("ALL ARENAS".to_string(), str::to_uppercase)
+        );
+    }
+
+    let font = asset_server.load("fonts/visitor.ttf");
+    let text_style = |color: Color| TextStyle {
+        font_size: 24.,
+        color,
+        font: font.clone(),
+    };
+    let header = [
+        "#", "NAME", "SCORE", "LENGTH", "TICKS", "MODE", "ARENA", "DATE",
+    ]
+    .map(str::to_string);
+    let entries: Vec<[String; 8]> = high_scores
+        .filtered(filter.mode, filter.arena.as_deref())
+        .take(TABLE_SIZE)
+        .enumerate()
+        .map(|(i, entry)| {
+            [
+                (i + 1).to_string(),
+                entry.name.to_uppercase(),
+                entry.score.to_string(),
+                entry.length.to_string(),
+                entry.ticks.to_string(),
+                mode_label(entry.mode).to_string(),
+                entry.arena.to_uppercase(),
+                date_label(entry.date),
+            ]
+        })
+        .collect();
+
+    for container in rows.iter() {
+        commands
+            .entity(container)
+            .despawn_descendants()
+            .with_children(|grid| {
+                for cell in header.iter() {
+                    grid.spawn(TextBundle::from_section(
+                        cell.clone(),
+                        text_style(MENU_SELECTED_COLOR),
+                    ));
+                }
+                for cell in entries.iter().flatten() {
+                    grid.spawn(TextBundle::from_section(
+                        cell.clone(),
+                        text_style(Color::WHITE),
+                    ));
+                }
+                if entries.is_empty() {
+                    grid.spawn(TextBundle::from_section(
+                        "NO SCORES YET",
+                        text_style(Color::WHITE),
+                    ))
+                    .insert(Style {
+                        grid_column: GridPlacement::span(8),
+                        ..default()
+                    });
+                }
+            });
+    }
+}
+
+pub fn open_high_score_screen(mut next_state: ResMut<NextState<HighScoreScreen>>) {
+    next_state.set(HighScoreScreen::Open);
+}
+
+/**
+ * Keeps the best results of every arena mode and arena between runs. New records are named by
+ * their players when a match ends, and the leaderboard can be opened from the main menu.
+ */
+pub struct HighScoresPlugin;
+impl Plugin for HighScoresPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_state::<HighScoreScreen>()
+            .enable_state_scoped_entities::<HighScoreScreen>()
+            .insert_resource(HighScores::load())
+            .add_systems(
+                OnEnter(GamePhase::Dead),
+                (
+                    check_for_records,
+                    spawn_record_prompt.run_if(resource_exists::<PendingRecords>),
+                )
+                    .chain()
+                    .run_if(not(
+                        resource_exists::<Playback>.or_else(resource_exists::<VersusMatch>)
+                    )),
+            )
+            .add_systems(OnExit(GameState::InGame), clear_pending_records)
+            .add_systems(
+                Update,
+                (record_name_entry, update_record_prompt)
+                    .chain()
+                    .run_if(resource_exists::<PendingRecords>),
+            )
+            .add_systems(OnEnter(HighScoreScreen::Open), spawn_leaderboard)
+            .add_systems(
+                Update,
+                (leaderboard_input, update_leaderboard)
+                    .chain()
+                    .run_if(in_state(HighScoreScreen::Open)),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, score: u32, arena: &str) -> HighScore {
+        HighScore {
+            name: name.to_string(),
+            score,
+            length: 0,
+            ticks: 0,
+            mode: ArenaMode::Walled,
+            arena: arena.to_string(),
+            date: 0,
+        }
+    }
+
+    fn names<'a>(high_scores: &'a HighScores, arena: &'a str) -> Vec<&'a str> {
+        high_scores
+            .filtered(Some(ArenaMode::Walled), Some(arena))
+            .map(|entry| entry.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn insert_keeps_the_best_first_and_the_older_of_a_tie_ahead() {
+        let mut high_scores = HighScores::default();
+        high_scores.insert(entry("first", 5, "open"));
+        high_scores.insert(entry("best", 7, "open"));
+        high_scores.insert(entry("second", 5, "open"));
+        high_scores.insert(entry("worst", 1, "open"));
+
+        assert_eq!(
+            names(&high_scores, "open"),
+            ["best", "first", "second", "worst"]
+        );
+    }
+
+    #[test]
+    fn insert_trims_every_table_to_its_size() {
+        let mut high_scores = HighScores::default();
+        high_scores.insert(entry("elsewhere", 1, "box"));
+        for score in 1..=TABLE_SIZE as u32 + 2 {
+            high_scores.insert(entry(&score.to_string(), score, "open"));
+        }
+
+        let open = names(&high_scores, "open");
+        assert_eq!(open.len(), TABLE_SIZE);
+        assert_eq!(open.first(), Some(&(TABLE_SIZE + 2).to_string().as_str()));
+        assert_eq!(open.last(), Some(&"3"));
+        assert_eq!(names(&high_scores, "box"), ["elsewhere"]);
+    }
+
+    #[test]
+    fn a_score_of_zero_never_qualifies() {
+        let high_scores = HighScores::default();
+        assert!(!high_scores.qualifies(ArenaMode::Walled, "open", 0));
+        assert!(high_scores.qualifies(ArenaMode::Walled, "open", 1));
+    }
+
+    #[test]
+    fn a_full_table_takes_only_scores_that_beat_its_last() {
+        let mut high_scores = HighScores::default();
+        for _ in 0..TABLE_SIZE {
+            high_scores.insert(entry("someone", 5, "open"));
+        }
+
+        assert!(!high_scores.qualifies(ArenaMode::Walled, "open", 5));
+        assert!(high_scores.qualifies(ArenaMode::Walled, "open", 6));
+        assert!(high_scores.qualifies(ArenaMode::Wrap, "open", 1));
+        assert!(high_scores.qualifies(ArenaMode::Walled, "box", 1));
+    }
+
+    #[test]
+    fn date_label_counts_days_from_the_epoch() {
+        assert_eq!(date_label(0), "1970-01-01");
+        assert_eq!(date_label(86_399), "1970-01-01");
+        assert_eq!(date_label(951_782_400), "2000-02-29");
+        assert_eq!(date_label(1_704_067_199), "2023-12-31");
+        assert_eq!(date_label(1_709_164_800), "2024-02-29");
+        assert_eq!(date_label(4_107_542_400), "2100-03-01");
+    }
+}
@@ -0,0 +1,80 @@
+use bevy::prelude::*;
+use bevy_asset_loader::prelude::AssetCollection;
+
+pub mod config;
+pub mod controls;
+pub mod enter_game;
+pub mod game;
+pub mod gamepad;
+pub mod headless;
+pub mod high_scores;
+pub mod interpolator;
+pub mod main_menu;
+pub mod menu;
+pub mod netplay;
+pub mod pause_menu;
+pub mod server;
+pub mod tournament;
+
+pub const SCREEN: Vec2 = Vec2::from_array([1280.0, 720.0]);
+pub const DARK: Color = Color::rgb(0.059, 0.219, 0.059);
+pub const LIGHT: Color = Color::rgb(0.852, 0.844, 0.816);
+
+// Example: Easy loading of assets
+#[derive(AssetCollection, Resource)]
+pub struct ImageAssets {
+    #[asset(texture_atlas(tile_size_x = 16, tile_size_y = 16, columns = 8, rows = 1))]
+    #[asset(path = "textures/chars/char_atlas.png")]
+    pub images: Handle<TextureAtlasLayout>,
+}
+
+#[derive(States, Hash, Clone, PartialEq, Eq, Debug, Default)]
+pub enum GameState {
+    #[default]
+    AssetLoading,
+    MainMenu,
+    EnterGame,
+    InGame,
+    LeaveGame,
+    /// Watching and steering a match that runs on a dedicated server.
+    RemoteGame,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct InGame;
+impl ComputedStates for InGame {
+    // Computed states can be calculated from one or many source states.
+    type SourceStates = GameState;
+
+    // Now, we define the rule that determines the value of our computed state.
+    fn compute(sources: GameState) -> Option<InGame> {
+        match sources {
+            // We can use pattern matching to express the
+            //"I don't care whether or not the game is paused" logic!
+            GameState::InGame { .. } => Some(InGame),
+            _ => None,
+        }
+    }
+}
+
+#[derive(SubStates, Clone, PartialEq, Eq, Hash, Debug, Default)]
+// This macro means that `GamePhase` will only exist when we're in the `InGame` computed state.
+// The intermediate computed state is helpful for clarity here, but isn't required:
+// you can manually `impl SubStates` for more control, multiple parent states and non-default initial value!
+#[source(InGame = InGame)]
+pub enum GamePhase {
+    #[default]
+    Playing,
+    Paused,
+    /// Between two rounds of a versus match.
+    RoundOver,
+    Dead,
+}
+
+pub fn add_game_states(app: &mut App) -> &mut App {
+    app.init_state::<GameState>()
+        .enable_state_scoped_entities::<GameState>()
+        .add_computed_state::<InGame>()
+        .add_sub_state::<GamePhase>()
+        .enable_state_scoped_entities::<GamePhase>()
+}
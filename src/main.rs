@@ -8,7 +8,7 @@ use bevy::{
 };
 use bevy_asset_loader::{
     loading_state::config::ConfigureLoadingState,
-    prelude::{AssetCollection, LoadingState, LoadingStateAppExt},
+    prelude::{LoadingState, LoadingStateAppExt},
 };
 use bevy_egui::EguiSettings;
 use bevy_egui::{
@@ -18,69 +18,32 @@ use bevy_egui::{
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_turborand::prelude::RngPlugin;
 use bevy_tween::DefaultTweenPlugins;
-use config::Debug;
-use enter_game::EnterGamePlugin;
-use game::{prelude::MainCamera, GamePlugin};
-use interpolator::custom_interpolators_plugin;
-use main_menu::*;
+use snek::{
+    add_game_states,
+    config::{self, Debug, Seed},
+    controls::ControlsPlugin,
+    enter_game::{lobby::Lobby, EnterGamePlugin},
+    game::{
+        brain::BrainRegistry,
+        grid::Grid,
+        level::{LevelAssets, SelectedLevel},
+        prelude::MainCamera,
+        remote::{RemotePlugin, RemoteSession},
+        replay::{start_playback, Replay},
+        rules::RulesAssets,
+        GamePlugin,
+    },
+    gamepad::GamepadPlugin,
+    headless,
+    high_scores::HighScoresPlugin,
+    interpolator::custom_interpolators_plugin,
+    main_menu::*,
+    netplay::{NetSession, NetplayPlugin},
+    pause_menu::PauseMenuPlugin,
+    tournament, GameState, ImageAssets, DARK, SCREEN,
+};
 use std::{env, process, time::Duration};
 
-mod config;
-mod enter_game;
-mod game;
-mod interpolator;
-mod main_menu;
-
-pub const SCREEN: Vec2 = Vec2::from_array([1280.0, 720.0]);
-pub const DARK: Color = Color::rgb(0.059, 0.219, 0.059);
-pub const LIGHT: Color = Color::rgb(0.852, 0.844, 0.816);
-
-// Example: Easy loading of assets
-#[derive(AssetCollection, Resource)]
-pub struct ImageAssets {
-    #[asset(texture_atlas(tile_size_x = 16, tile_size_y = 16, columns = 8, rows = 1))]
-    #[asset(path = "textures/chars/char_atlas.png")]
-    pub images: Handle<TextureAtlasLayout>,
-}
-
-#[derive(States, Hash, Clone, PartialEq, Eq, Debug, Default)]
-pub enum GameState {
-    #[default]
-    AssetLoading,
-    MainMenu,
-    EnterGame,
-    InGame,
-    LeaveGame,
-}
-
-#[derive(Clone, PartialEq, Eq, Hash, Debug)]
-struct InGame;
-impl ComputedStates for InGame {
-    // Computed states can be calculated from one or many source states.
-    type SourceStates = GameState;
-
-    // Now, we define the rule that determines the value of our computed state.
-    fn compute(sources: GameState) -> Option<InGame> {
-        match sources {
-            // We can use pattern matching to express the
-            //"I don't care whether or not the game is paused" logic!
-            GameState::InGame { .. } => Some(InGame),
-            _ => None,
-        }
-    }
-}
-
-#[derive(SubStates, Clone, PartialEq, Eq, Hash, Debug, Default)]
-// This macro means that `GamePhase` will only exist when we're in the `InGame` computed state.
-// The intermediate computed state is helpful for clarity here, but isn't required:
-// you can manually `impl SubStates` for more control, multiple parent states and non-default initial value!
-#[source(InGame = InGame)]
-enum GamePhase {
-    #[default]
-    Playing,
-    Paused,
-    Dead,
-}
 /**
  * The configuration for the game loop. For cleanliness
  */
@@ -92,6 +55,35 @@ fn main() {
         process::exit(1);
     });
 
+    if cfg.list_bots {
+        for name in BrainRegistry::default().names() {
+            println!("{name}");
+        }
+        return;
+    }
+    if let Some(bots) = &cfg.tournament {
+        tournament::run(&cfg, bots);
+        return;
+    }
+    if cfg.headless {
+        headless::run(&cfg);
+        return;
+    }
+
+    let playback = cfg.replay.as_ref().map(|path| {
+        Replay::load(path).unwrap_or_else(|err| {
+            println!("Could not load replay {}: {err}", path.display());
+            process::exit(1);
+        })
+    });
+    // a replay goes straight to the match it recorded, and bots picked with `--ai` to the lobby
+    let first_state = match (&playback, cfg.connect, cfg.ai.0.is_empty()) {
+        (Some(_), _, _) => GameState::InGame,
+        (None, Some(_), _) => GameState::RemoteGame,
+        (None, None, false) => GameState::EnterGame,
+        (None, None, true) => GameState::MainMenu,
+    };
+
     let mut app = App::new();
     app.add_plugins((
         DefaultPlugins
@@ -115,32 +107,67 @@ fn main() {
             .set(ImagePlugin::default_nearest()),
         DefaultTweenPlugins,
         custom_interpolators_plugin,
-    ))
-    .init_state::<GameState>()
-    .enable_state_scoped_entities::<GameState>()
-    .add_computed_state::<InGame>()
-    .add_sub_state::<GamePhase>()
-    .insert_resource(Debug(cfg.debug))
-    // Example: Easy loading of assets
-    .add_loading_state(
-        LoadingState::new(GameState::AssetLoading)
-            .continue_to_state(GameState::EnterGame)
-            .load_collection::<ImageAssets>(),
-    )
-    .insert_resource(Debug(cfg.debug))
-    .add_plugins(
-        WorldInspectorPlugin::default().run_if(input_toggle_active(false, KeyCode::Escape)),
-    )
-    .add_plugins((
-        FrameTimeDiagnosticsPlugin::default(),
-        RngPlugin::new().with_rng_seed(220718),
-        // EguiPlugin,
-        MainMenuPlugin,
-        EnterGamePlugin,
-        GamePlugin,
-    ))
-    .add_systems(Startup, (setup_camera, setup_fonts))
-    .add_systems(Update, window_resized);
+    ));
+    add_game_states(&mut app)
+        .insert_resource(Debug(cfg.debug))
+        .insert_resource(Seed(cfg.seed))
+        .insert_resource(match (cfg.net, cfg.ai.0.len()) {
+            (Some(_), _) => Lobby::with_players(2, &cfg.ai),
+            (None, 0) => Lobby::default(),
+            (None, bots) => Lobby::with_players(bots, &cfg.ai),
+        })
+        .insert_resource(cfg.brain_registry())
+        // Example: Easy loading of assets
+        .add_loading_state(
+            LoadingState::new(GameState::AssetLoading)
+                .continue_to_state(first_state)
+                .load_collection::<ImageAssets>()
+                .load_collection::<LevelAssets>()
+                .load_collection::<RulesAssets>(),
+        )
+        .add_plugins(
+            // on a key of its own, as Escape already backs out of menus
+            WorldInspectorPlugin::default().run_if(input_toggle_active(false, KeyCode::F12)),
+        )
+        .add_plugins((
+            FrameTimeDiagnosticsPlugin::default(),
+            RngPlugin::new().with_rng_seed(cfg.seed),
+            // EguiPlugin,
+            MainMenuPlugin,
+            ControlsPlugin,
+            EnterGamePlugin,
+            GamePlugin,
+            GamepadPlugin,
+            PauseMenuPlugin,
+            HighScoresPlugin,
+        ))
+        .insert_resource(Grid {
+            mode: cfg.arena_mode,
+            ..default()
+        })
+        .insert_resource(SelectedLevel(cfg.level))
+        .add_systems(Startup, (setup_camera, setup_fonts))
+        .add_systems(Update, window_resized);
+
+    if let Some(role) = cfg.net {
+        let session = NetSession::open(role).unwrap_or_else(|err| {
+            println!("Could not open a network socket: {err}");
+            process::exit(1);
+        });
+        app.add_plugins(NetplayPlugin).insert_resource(session);
+    }
+
+    if let Some(server) = cfg.connect {
+        let session = RemoteSession::connect(server, cfg.name.clone()).unwrap_or_else(|err| {
+            println!("Could not open a network socket: {err}");
+            process::exit(1);
+        });
+        app.add_plugins(RemotePlugin).insert_resource(session);
+    }
+
+    if let Some(replay) = playback {
+        start_playback(app.world_mut(), replay);
+    }
 
     app.run();
 }
@@ -1,4 +1,89 @@
-use bevy::prelude::Component;
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+
+use crate::menu::MenuCursor;
+
+/// The column the entries of the current page are spawned in.
+#[derive(Component)]
+pub struct MainMenuEntries;
 
 #[derive(Component)]
-pub struct MainMenuText;
+pub struct MainMenuMessage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MainMenuEntry {
+    LocalPlay,
+    VersusAi,
+    Online,
+    Replays,
+    HighScores,
+    Settings,
+    Quit,
+}
+
+pub const MAIN_MENU_ENTRIES: [MainMenuEntry; 7] = [
+    MainMenuEntry::LocalPlay,
+    MainMenuEntry::VersusAi,
+    MainMenuEntry::Online,
+    MainMenuEntry::Replays,
+    MainMenuEntry::HighScores,
+    MainMenuEntry::Settings,
+    MainMenuEntry::Quit,
+];
+
+impl MainMenuEntry {
+    pub fn label(&self) -> &'static str {
+        match self {
+            MainMenuEntry::LocalPlay => "LOCAL PLAY",
+            MainMenuEntry::VersusAi => "VERSUS AI",
+            MainMenuEntry::Online => "ONLINE",
+            MainMenuEntry::Replays => "REPLAYS",
+            MainMenuEntry::HighScores => "HIGH SCORES",
+            MainMenuEntry::Settings => "SETTINGS",
+            MainMenuEntry::Quit => "QUIT",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MainMenuPage {
+    Main,
+    /// The saved replays to pick from, each with its label.
+    Replays(Vec<(PathBuf, String)>),
+}
+
+#[derive(Resource)]
+pub struct MainMenu {
+    pub page: MainMenuPage,
+    pub cursor: MenuCursor,
+    pub message: String,
+}
+
+impl MainMenu {
+    pub fn labels(&self) -> Vec<&str> {
+        match &self.page {
+            MainMenuPage::Main => MAIN_MENU_ENTRIES.iter().map(MainMenuEntry::label).collect(),
+            MainMenuPage::Replays(replays) => replays
+                .iter()
+                .map(|(_, label)| label.as_str())
+                .chain(std::iter::once("BACK"))
+                .collect(),
+        }
+    }
+
+    pub fn show(&mut self, page: MainMenuPage) {
+        self.page = page;
+        self.cursor = MenuCursor::new(self.labels().len());
+    }
+}
+
+impl Default for MainMenu {
+    fn default() -> Self {
+        MainMenu {
+            page: MainMenuPage::Main,
+            cursor: MenuCursor::new(MAIN_MENU_ENTRIES.len()),
+            message: String::new(),
+        }
+    }
+}
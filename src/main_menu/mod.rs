@@ -1,20 +1,29 @@
-use self::systems::{setup, teardown, transition_to_game};
-use crate::GameState;
+use self::{
+    components::MainMenu,
+    systems::{main_menu_input, setup, update_main_menu},
+};
+use crate::{controls::ControlsScreen, high_scores::HighScoreScreen, GameState};
 use bevy::prelude::*;
 mod components;
 mod systems;
 /**
- * sett state for lukking
- * hello world fader ut
+ * The title screen the game starts on, leading to the lobby, replays, settings and the rest.
  */
 pub struct MainMenuPlugin;
 impl Plugin for MainMenuPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(GameState::MainMenu), setup)
+        app.init_resource::<MainMenu>()
+            .add_systems(OnEnter(GameState::MainMenu), setup)
             .add_systems(
                 Update,
-                transition_to_game.run_if(in_state(GameState::MainMenu)),
-            )
-            .add_systems(OnExit(GameState::MainMenu), teardown);
+                (
+                    main_menu_input
+                        .run_if(in_state(ControlsScreen::Closed))
+                        .run_if(in_state(HighScoreScreen::Closed)),
+                    update_main_menu,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::MainMenu)),
+            );
     }
 }
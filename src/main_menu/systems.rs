@@ -1,68 +1,218 @@
 use bevy::prelude::*;
 
-use crate::GameState;
+use crate::{
+    controls::ControlsScreen,
+    enter_game::lobby::Lobby,
+    game::{
+        ai::AiSlots,
+        brain::BrainRegistry,
+        replay::{start_playback, Replay},
+    },
+    high_scores::HighScoreScreen,
+    menu::{spawn_menu_entries, MenuAction, MenuInput},
+    netplay::NetSession,
+    GameState,
+};
 
-use super::components::MainMenuText;
+use super::components::{
+    MainMenu, MainMenuEntries, MainMenuEntry, MainMenuMessage, MainMenuPage, MAIN_MENU_ENTRIES,
+};
 
-pub fn transition_to_game(
+/// How many of the newest replays the replays page lists.
+const REPLAYS_SHOWN: usize = 8;
+
+fn replay_label(replay: &Replay) -> String {
+    let names: Vec<String> = replay
+        .players
+        .iter()
+        .map(|player| player.name.to_uppercase())
+        .collect();
+    format!("{}  {} TICKS", names.join(" VS "), replay.ticks())
+}
+
+fn replays_page() -> MainMenuPage {
+    MainMenuPage::Replays(
+        Replay::list()
+            .into_iter()
+            .filter_map(|path| {
+                let label = replay_label(&Replay::load(&path).ok()?);
+                Some((path, label))
+            })
+            .take(REPLAYS_SHOWN)
+            .collect(),
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn choose_entry(
+    entry: MainMenuEntry,
+    menu: &mut MainMenu,
+    lobby: &mut Lobby,
+    registry: &BrainRegistry,
+    online: bool,
+    next_state: &mut NextState<GameState>,
+    next_controls: &mut NextState<ControlsScreen>,
+    next_high_scores: &mut NextState<HighScoreScreen>,
+    exit: &mut EventWriter<AppExit>,
+) {
+    match entry {
+        MainMenuEntry::LocalPlay | MainMenuEntry::VersusAi if online => {
+            menu.message = "RESTART WITHOUT --host OR --join TO PLAY LOCALLY".to_string();
+        }
+        MainMenuEntry::LocalPlay => {
+            lobby.slots.retain(|slot| slot.bot.is_none());
+            next_state.set(GameState::EnterGame);
+        }
+        MainMenuEntry::VersusAi => {
+            let bot = registry.names().next().map(str::to_string);
+            *lobby = Lobby::with_players(2, &AiSlots(vec![None, bot]));
+            next_state.set(GameState::EnterGame);
+        }
+        MainMenuEntry::Online if online => next_state.set(GameState::EnterGame),
+        MainMenuEntry::Online => {
+            menu.message = "START WITH --host PORT OR --join ADDRESS TO PLAY ONLINE".to_string();
+        }
+        MainMenuEntry::Replays => {
+            menu.show(replays_page());
+            menu.message.clear();
+        }
+        MainMenuEntry::HighScores => next_high_scores.set(HighScoreScreen::Open),
+        MainMenuEntry::Settings => next_controls.set(ControlsScreen::Open),
+        MainMenuEntry::Quit => {
+            exit.send(AppExit::Success);
+        }
+    }
+}
+
+/**
+ * Moves through the menu and acts on the picked entry. Going back from a page returns to the
+ * main page.
+ */
+#[allow(clippy::too_many_arguments)]
+pub fn main_menu_input(
+    mut commands: Commands,
+    input: MenuInput,
+    mut menu: ResMut<MainMenu>,
+    mut lobby: ResMut<Lobby>,
+    registry: Res<BrainRegistry>,
+    net_session: Option<Res<NetSession>>,
     mut next_state: ResMut<NextState<GameState>>,
-    keyboard: Res<ButtonInput<KeyCode>>,
+    mut next_controls: ResMut<NextState<ControlsScreen>>,
+    mut next_high_scores: ResMut<NextState<HighScoreScreen>>,
+    mut exit: EventWriter<AppExit>,
 ) {
-    if keyboard.pressed(KeyCode::Space) {
-        next_state.set(GameState::InGame);
+    for action in input.actions() {
+        if menu.cursor.navigate(action) {
+            continue;
+        }
+        let selected = menu.cursor.selected;
+        match (&menu.page, action) {
+            (_, MenuAction::Left | MenuAction::Right) => continue,
+            (MainMenuPage::Main, MenuAction::Back) => {}
+            (MainMenuPage::Main, _) => choose_entry(
+                MAIN_MENU_ENTRIES[selected],
+                &mut menu,
+                &mut lobby,
+                &registry,
+                net_session.is_some(),
+                &mut next_state,
+                &mut next_controls,
+                &mut next_high_scores,
+                &mut exit,
+            ),
+            (MainMenuPage::Replays(replays), MenuAction::Select) if selected < replays.len() => {
+                let path = replays[selected].0.clone();
+                match Replay::load(&path) {
+                    Ok(replay) => {
+                        commands.add(move |world: &mut World| start_playback(world, replay));
+                        next_state.set(GameState::InGame);
+                    }
+                    Err(err) => {
+                        warn!("could not load replay {}: {err}", path.display());
+                        menu.message = "COULD NOT LOAD THAT REPLAY".to_string();
+                    }
+                }
+            }
+            (MainMenuPage::Replays(_), _) => {
+                menu.show(MainMenuPage::Main);
+                menu.message.clear();
+            }
+        }
+        return;
     }
 }
 
 pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    // Text with multiple sections
-    commands.spawn((
-        // Create a TextBundle that has a Text with a list of sections.
-        TextBundle::from_sections([TextSection::new(
-            "GAME TITLE!",
-            TextStyle {
-                font: asset_server.load("fonts/visitor.ttf"),
-                font_size: 40.0,
-                color: Color::WHITE,
-            },
-        )])
-        .with_style(Style {
-            margin: UiRect {
-                top: Val::Px(32.0),
-                right: Val::Auto,
-                left: Val::Auto,
-                ..default()
-            },
+    let font = asset_server.load("fonts/visitor.ttf");
+    let text_style = |font_size: f32| TextStyle {
+        font_size,
+        color: Color::WHITE,
+        font: font.clone(),
+    };
+    commands.insert_resource(MainMenu::default());
 
-            ..default()
-        }),
-        MainMenuText,
-    ));
-    commands.spawn((
-        // Create a TextBundle that has a Text with a list of sections.
-        TextBundle::from_sections([TextSection::new(
-            "Press space to continue...",
-            TextStyle {
-                font: asset_server.load("fonts/visitor.ttf"),
-                font_size: 24.0,
-                color: Color::WHITE,
-            },
-        )])
-        .with_style(Style {
-            position_type: PositionType::Absolute,
-            top: Val::Percent(30.0),
-            margin: UiRect {
-                left: Val::Auto,
-                right: Val::Auto,
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(24.),
+                    ..default()
+                },
                 ..default()
             },
-            ..default()
-        }),
-        MainMenuText,
-    ));
+            StateScoped(GameState::MainMenu),
+        ))
+        .with_children(|builder| {
+            builder.spawn(TextBundle::from_section("SNEK SNAKS", text_style(80.)));
+            builder.spawn((
+                NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        row_gap: Val::Px(8.),
+                        ..default()
+                    },
+                    ..default()
+                },
+                MainMenuEntries,
+            ));
+            builder.spawn((
+                TextBundle::from_section("", text_style(24.)),
+                MainMenuMessage,
+            ));
+            builder.spawn(TextBundle::from_section(
+                "ARROWS OR D-PAD: PICK  ENTER OR A: CHOOSE  ESC OR B: BACK",
+                text_style(20.),
+            ));
+        });
 }
 
-pub fn teardown(mut commands: Commands, texts: Query<Entity, With<MainMenuText>>) {
-    for entity in texts.iter() {
-        commands.entity(entity).despawn();
+/// Respawns the entries of the current page whenever the menu changes.
+pub fn update_main_menu(
+    mut commands: Commands,
+    menu: Res<MainMenu>,
+    asset_server: Res<AssetServer>,
+    containers: Query<Entity, With<MainMenuEntries>>,
+    mut messages: Query<&mut Text, With<MainMenuMessage>>,
+) {
+    if !menu.is_changed() {
+        return;
+    }
+    let font = asset_server.load("fonts/visitor.ttf");
+    for container in containers.iter() {
+        commands
+            .entity(container)
+            .despawn_descendants()
+            .with_children(|builder| {
+                spawn_menu_entries(builder, &menu.labels(), menu.cursor.selected, font.clone());
+            });
+    }
+    for mut text in messages.iter_mut() {
+        text.sections[0].value.clone_from(&menu.message);
     }
 }
@@ -0,0 +1,128 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{game::prelude::SnakeDirection, gamepad::GamepadDirections};
+
+pub const MENU_TEXT_COLOR: Color = Color::WHITE;
+pub const MENU_SELECTED_COLOR: Color = Color::srgb(1., 0.8, 0.2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuAction {
+    Up,
+    Down,
+    Left,
+    Right,
+    Select,
+    Back,
+}
+
+/**
+ * Menu navigation from the keyboard and every gamepad: the arrow keys, D-pad or stick move,
+ * enter or the south button picks and escape or the east button goes back.
+ */
+#[derive(SystemParam)]
+pub struct MenuInput<'w> {
+    keyboard: Res<'w, ButtonInput<KeyCode>>,
+    buttons: Res<'w, ButtonInput<GamepadButton>>,
+    directions: Res<'w, GamepadDirections>,
+}
+
+impl MenuInput<'_> {
+    pub fn actions(&self) -> Vec<MenuAction> {
+        let keys = self
+            .keyboard
+            .get_just_pressed()
+            .filter_map(|key| match key {
+                KeyCode::ArrowUp => Some(MenuAction::Up),
+                KeyCode::ArrowDown => Some(MenuAction::Down),
+                KeyCode::ArrowLeft => Some(MenuAction::Left),
+                KeyCode::ArrowRight => Some(MenuAction::Right),
+                KeyCode::Enter => Some(MenuAction::Select),
+                KeyCode::Escape => Some(MenuAction::Back),
+                _ => None,
+            });
+        let buttons =
+            self.buttons
+                .get_just_pressed()
+                .filter_map(|button| match button.button_type {
+                    GamepadButtonType::South => Some(MenuAction::Select),
+                    GamepadButtonType::East => Some(MenuAction::Back),
+                    _ => None,
+                });
+        let sticks = self
+            .directions
+            .just_pressed
+            .iter()
+            .map(|(_, direction)| match direction {
+                SnakeDirection::Up => MenuAction::Up,
+                SnakeDirection::Down => MenuAction::Down,
+                SnakeDirection::Left => MenuAction::Left,
+                SnakeDirection::Right => MenuAction::Right,
+            });
+        keys.chain(buttons).chain(sticks).collect()
+    }
+}
+
+/// One of the entries of a menu, by its position.
+#[derive(Component)]
+pub struct MenuEntry(pub usize);
+
+/**
+ * A list of entries with one of them selected. Moving past either end wraps around.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct MenuCursor {
+    pub selected: usize,
+    pub len: usize,
+}
+
+impl MenuCursor {
+    pub fn new(len: usize) -> Self {
+        MenuCursor { selected: 0, len }
+    }
+
+    /// Moves the selection for `Up` and `Down`, and returns whether it did.
+    pub fn navigate(&mut self, action: MenuAction) -> bool {
+        match action {
+            MenuAction::Up => self.selected = (self.selected + self.len - 1) % self.len,
+            MenuAction::Down => self.selected = (self.selected + 1) % self.len,
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Spawns a column of menu entries, with the one at `selected` highlighted.
+pub fn spawn_menu_entries(
+    builder: &mut ChildBuilder,
+    labels: &[&str],
+    selected: usize,
+    font: Handle<Font>,
+) {
+    for (i, label) in labels.iter().enumerate() {
+        builder.spawn((
+            TextBundle::from_section(
+                *label,
+                TextStyle {
+                    font_size: 40.,
+                    color: if i == selected {
+                        MENU_SELECTED_COLOR
+                    } else {
+                        MENU_TEXT_COLOR
+                    },
+                    font: font.clone(),
+                },
+            ),
+            MenuEntry(i),
+        ));
+    }
+}
+
+pub fn highlight_menu_entries(cursor: &MenuCursor, entries: &mut Query<(&mut Text, &MenuEntry)>) {
+    for (mut text, entry) in entries.iter_mut() {
+        text.sections[0].style.color = if entry.0 == cursor.selected {
+            MENU_SELECTED_COLOR
+        } else {
+            MENU_TEXT_COLOR
+        };
+    }
+}
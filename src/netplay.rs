@@ -0,0 +1,443 @@
+use std::{
+    collections::VecDeque,
+    io,
+    net::{SocketAddr, UdpSocket},
+};
+
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Seed,
+    enter_game::lobby::LobbySeat,
+    game::{
+        components::{MatchSetup, MatchTick, TickSet},
+        grid::{ArenaMode, Grid},
+        level::SelectedLevel,
+        prelude::{ControlScheme, DirectionQueue, Player, SnakeDirection, DIRECTION_QUEUE_SIZE},
+        rules::GameRules,
+    },
+    GamePhase, GameState,
+};
+
+/// Ticks between a turn being pressed and the snake taking it, to give it time to reach the peer.
+const INPUT_DELAY: usize = 3;
+/// How many of the latest ticks of input go into every packet, so a lost packet is made up for
+/// by the next one.
+const RESEND_WINDOW: usize = 32;
+/// Seconds between repeats of the handshake and start messages until they are answered, and of
+/// the latest inputs while waiting on the peer.
+const RESEND_INTERVAL: f32 = 0.5;
+/// Seconds without the peer's inputs before the match is given up on.
+const PEER_TIMEOUT: f32 = 10.;
+const MAX_PACKET_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy)]
+pub enum NetRole {
+    /// Wait for a peer on the given UDP port.
+    Host(u16),
+    Join(SocketAddr),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+enum NetMessage {
+    Hello,
+    Welcome,
+    Start {
+        match_id: u32,
+        seed: u64,
+        level: usize,
+        arena_mode: ArenaMode,
+        rules: GameRules,
+    },
+    /// Turns of the sender's snake from `first_tick` on, one character per tick.
+    Inputs {
+        match_id: u32,
+        first_tick: usize,
+        inputs: String,
+    },
+}
+
+/**
+ * A two player game over UDP in lockstep. Both peers run the full simulation and only exchange
+ * the turns of their own snake, which are applied `INPUT_DELAY` ticks after they were pressed.
+ * A tick does not run until the turns of both snakes for it have arrived.
+ */
+#[derive(Resource)]
+pub struct NetSession {
+    socket: UdpSocket,
+    role: NetRole,
+    peer: Option<SocketAddr>,
+    match_id: u32,
+    /// Whether the peer has answered the current handshake or start message.
+    answered: bool,
+    since_resend: f32,
+    /// Seconds the match has been waiting on the peer's inputs.
+    stalled_for: f32,
+    since_inputs_sent: f32,
+    /// The tick the next `exchange_inputs` runs.
+    tick: usize,
+    /// Turns pressed locally that have not been scheduled for a tick yet.
+    pending: VecDeque<SnakeDirection>,
+    local: Vec<char>,
+    remote: Vec<Option<char>>,
+}
+
+impl NetSession {
+    pub fn open(role: NetRole) -> io::Result<Self> {
+        let (bind_addr, peer) = match role {
+            NetRole::Host(port) => (SocketAddr::from(([0, 0, 0, 0], port)), None),
+            NetRole::Join(addr) => (SocketAddr::from(([0, 0, 0, 0], 0)), Some(addr)),
+        };
+        let socket = UdpSocket::bind(bind_addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(NetSession {
+            socket,
+            role,
+            peer,
+            match_id: 0,
+            answered: false,
+            since_resend: RESEND_INTERVAL,
+            stalled_for: 0.,
+            since_inputs_sent: 0.,
+            tick: 0,
+            pending: VecDeque::new(),
+            local: Vec::new(),
+            remote: Vec::new(),
+        })
+    }
+
+    fn is_host(&self) -> bool {
+        matches!(self.role, NetRole::Host(_))
+    }
+
+    /// The host plays the first snake, the peer that joined the second.
+    fn local_slot(&self) -> usize {
+        if self.is_host() {
+            0
+        } else {
+            1
+        }
+    }
+
+    fn send(&self, message: &NetMessage) {
+        let Some(peer) = self.peer else {
+            return;
+        };
+        let sent = serde_json::to_vec(message)
+            .map_err(io::Error::from)
+            .and_then(|bytes| self.socket.send_to(&bytes, peer));
+        if let Err(err) = sent {
+            warn!("could not send to {peer}: {err}");
+        }
+    }
+
+    fn receive(&self) -> Option<(NetMessage, SocketAddr)> {
+        let mut buf = [0; MAX_PACKET_SIZE];
+        loop {
+            match self.socket.recv_from(&mut buf) {
+                Ok((len, from)) => match serde_json::from_slice(&buf[..len]) {
+                    Ok(message) => return Some((message, from)),
+                    Err(err) => warn!("ignoring bad packet from {from}: {err}"),
+                },
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return None,
+                Err(err) => {
+                    // on some platforms an unreachable peer shows up as a receive error
+                    debug!("receive failed: {err}");
+                    return None;
+                }
+            }
+        }
+    }
+
+    fn begin_match(&mut self, match_id: u32) {
+        self.match_id = match_id;
+        self.tick = 0;
+        self.stalled_for = 0.;
+        self.pending.clear();
+        // nobody turns during the first ticks, before the first inputs can arrive
+        self.local = vec!['.'; INPUT_DELAY];
+        self.remote = vec![Some('.'); INPUT_DELAY];
+    }
+
+    fn inputs_ready(&self) -> bool {
+        self.local.len() > self.tick && self.remote.get(self.tick).is_some_and(Option::is_some)
+    }
+
+    fn send_inputs(&self) {
+        let first_tick = self.local.len().saturating_sub(RESEND_WINDOW);
+        self.send(&NetMessage::Inputs {
+            match_id: self.match_id,
+            first_tick,
+            inputs: self.local[first_tick..].iter().collect(),
+        });
+    }
+}
+
+fn inputs_ready(session: Res<NetSession>) -> bool {
+    session.inputs_ready()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn receive_messages(
+    mut session: ResMut<NetSession>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut seed: ResMut<Seed>,
+    mut selected_level: ResMut<SelectedLevel>,
+    mut grid: ResMut<Grid>,
+    mut rules: ResMut<GameRules>,
+) {
+    while let Some((message, from)) = session.receive() {
+        match message {
+            NetMessage::Hello if session.is_host() => {
+                if session.peer != Some(from) {
+                    info!("{from} joined");
+                    session.peer = Some(from);
+                }
+                session.send(&NetMessage::Welcome);
+            }
+            NetMessage::Welcome => {
+                session.answered = true;
+            }
+            NetMessage::Start {
+                match_id,
+                seed: match_seed,
+                level,
+                arena_mode,
+                rules: match_rules,
+            } if !session.is_host() => {
+                // starts are repeated until answered, and can only be acted on from the lobby
+                if match_id <= session.match_id || *state.get() != GameState::EnterGame {
+                    continue;
+                }
+                seed.0 = match_seed;
+                selected_level.0 = level;
+                grid.mode = arena_mode;
+                *rules = match_rules;
+                session.begin_match(match_id);
+                next_state.set(GameState::InGame);
+            }
+            NetMessage::Inputs {
+                match_id,
+                first_tick,
+                inputs,
+            } if match_id == session.match_id => {
+                session.answered = true;
+                let last_tick = first_tick + inputs.chars().count();
+                if session.remote.len() < last_tick {
+                    session.remote.resize(last_tick, None);
+                }
+                for (i, c) in inputs.chars().enumerate() {
+                    session.remote[first_tick + i] = Some(c);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/**
+ * Repeats the handshake, and the start of a match on the host, until the peer answers.
+ */
+fn resend_unanswered(
+    time: Res<Time<Real>>,
+    mut session: ResMut<NetSession>,
+    seed: Res<Seed>,
+    selected_level: Res<SelectedLevel>,
+    grid: Res<Grid>,
+    rules: Res<GameRules>,
+) {
+    session.since_resend += time.delta_seconds();
+    if session.answered || session.since_resend < RESEND_INTERVAL {
+        return;
+    }
+    session.since_resend = 0.;
+
+    if !session.is_host() {
+        session.send(&NetMessage::Hello);
+    } else if session.is_host() && session.match_id > 0 {
+        session.send(&NetMessage::Start {
+            match_id: session.match_id,
+            seed: seed.0,
+            level: selected_level.0,
+            arena_mode: grid.mode,
+            rules: rules.clone(),
+        });
+    }
+}
+
+/**
+ * While a tick is held up waiting on the peer, sends the latest inputs again every so often, in
+ * case the last ones were lost and the peer is waiting too. Goes back to the lobby once the peer
+ * has not been heard from for too long.
+ */
+fn resend_inputs(
+    time: Res<Time<Real>>,
+    mut session: ResMut<NetSession>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if session.inputs_ready() {
+        session.stalled_for = 0.;
+        return;
+    }
+    session.stalled_for += time.delta_seconds();
+    session.since_inputs_sent += time.delta_seconds();
+    if session.stalled_for > PEER_TIMEOUT {
+        warn!("no inputs from the peer for {PEER_TIMEOUT} seconds, leaving the match");
+        if session.is_host() {
+            // wait for someone to join again, without starting them into the old match
+            session.peer = None;
+            session.answered = true;
+        } else {
+            // say hello again, so a host that is still there knows this peer is back
+            session.answered = false;
+        }
+        session.stalled_for = 0.;
+        next_state.set(GameState::EnterGame);
+        return;
+    }
+    if session.since_inputs_sent >= RESEND_INTERVAL {
+        session.since_inputs_sent = 0.;
+        session.send_inputs();
+    }
+}
+
+/// The host starts a match with space once a peer has joined.
+fn host_start_keys(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut session: ResMut<NetSession>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !session.is_host() || session.peer.is_none() || !keyboard.just_pressed(KeyCode::Space) {
+        return;
+    }
+    let match_id = session.match_id + 1;
+    session.begin_match(match_id);
+    session.answered = false;
+    session.since_resend = RESEND_INTERVAL;
+    next_state.set(GameState::InGame);
+}
+
+/**
+ * The local player steers with either set of keys, the remote snake only moves by the turns
+ * received from the peer.
+ */
+fn assign_net_controls(
+    session: Res<NetSession>,
+    mut players: Query<(&mut ControlScheme, &LobbySeat), With<Player>>,
+) {
+    let mut all_keys = HashMap::new();
+    for (controls, _) in players.iter() {
+        all_keys.extend(controls.directional_controls.clone());
+    }
+    for (mut controls, seat) in players.iter_mut() {
+        controls.directional_controls = if seat.0 == session.local_slot() {
+            all_keys.clone()
+        } else {
+            HashMap::new()
+        };
+    }
+}
+
+/**
+ * Schedules the next local turn `INPUT_DELAY` ticks ahead and sends it, then hands both snakes
+ * the turns scheduled for this tick.
+ */
+fn exchange_inputs(
+    mut session: ResMut<NetSession>,
+    mut players: Query<(&mut DirectionQueue, &LobbySeat), With<Player>>,
+) {
+    let session = &mut *session;
+    let local_slot = session.local_slot();
+
+    for (mut queue, seat) in players.iter_mut() {
+        if seat.0 == local_slot {
+            session.pending.extend(queue.0.drain(..));
+            session.pending.truncate(DIRECTION_QUEUE_SIZE);
+        }
+    }
+    let next = session.pending.pop_front();
+    session.local.push(next.map_or('.', |dir| dir.to_char()));
+    session.send_inputs();
+
+    let tick = session.tick;
+    for (mut queue, seat) in players.iter_mut() {
+        let input = if seat.0 == local_slot {
+            Some(session.local[tick])
+        } else {
+            session.remote[tick]
+        };
+        queue.0.clear();
+        queue.0.extend(input.and_then(SnakeDirection::from_char));
+    }
+    session.tick += 1;
+}
+
+#[derive(Component)]
+struct NetStatusText;
+
+fn spawn_net_status(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(8.),
+                top: Val::Px(8.),
+                ..default()
+            },
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font_size: 30.,
+                    color: Color::WHITE,
+                    font: asset_server.load("fonts/visitor.ttf"),
+                },
+            ),
+            ..default()
+        },
+        NetStatusText,
+        StateScoped(GameState::EnterGame),
+    ));
+}
+
+fn update_net_status(session: Res<NetSession>, mut texts: Query<&mut Text, With<NetStatusText>>) {
+    let status = match (session.role, session.peer, session.answered) {
+        (NetRole::Host(port), None, _) => format!("WAITING FOR A PLAYER ON PORT {port}"),
+        (NetRole::Host(_), Some(peer), _) => format!("{peer} JOINED - SPACE TO START"),
+        (NetRole::Join(addr), _, false) => format!("CONNECTING TO {addr}"),
+        (NetRole::Join(_), _, true) => "CONNECTED - WAITING FOR THE HOST TO START".to_string(),
+    };
+    for mut text in texts.iter_mut() {
+        text.sections[0].value.clone_from(&status);
+    }
+}
+
+/**
+ * Online play for two over UDP, started with `--host PORT` or `--join ADDRESS`.
+ * Needs a `NetSession` resource.
+ */
+pub struct NetplayPlugin;
+impl Plugin for NetplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PreUpdate, receive_messages)
+            .add_systems(Update, resend_unanswered)
+            .add_systems(Update, resend_inputs.run_if(in_state(GamePhase::Playing)))
+            .add_systems(OnEnter(GameState::EnterGame), spawn_net_status)
+            .add_systems(
+                Update,
+                (host_start_keys, update_net_status).run_if(in_state(GameState::EnterGame)),
+            )
+            .add_systems(
+                OnEnter(GameState::InGame),
+                assign_net_controls.before(MatchSetup),
+            )
+            .add_systems(
+                FixedUpdate,
+                exchange_inputs
+                    .in_set(TickSet::Input)
+                    .run_if(in_state(GamePhase::Playing)),
+            )
+            .configure_sets(FixedUpdate, MatchTick.run_if(inputs_ready));
+    }
+}
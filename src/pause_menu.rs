@@ -0,0 +1,147 @@
+use bevy::prelude::*;
+
+use crate::{
+    controls::ControlsScreen,
+    menu::{
+        highlight_menu_entries, spawn_menu_entries, MenuAction, MenuCursor, MenuEntry, MenuInput,
+    },
+    GamePhase, GameState,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PauseEntry {
+    Resume,
+    Restart,
+    Settings,
+    MainMenu,
+    Quit,
+}
+
+impl PauseEntry {
+    fn label(&self) -> &'static str {
+        match self {
+            PauseEntry::Resume => "RESUME",
+            PauseEntry::Restart => "RESTART MATCH",
+            PauseEntry::Settings => "SETTINGS",
+            PauseEntry::MainMenu => "MAIN MENU",
+            PauseEntry::Quit => "QUIT",
+        }
+    }
+}
+
+#[derive(Resource)]
+struct PauseMenu {
+    entries: Vec<PauseEntry>,
+    cursor: MenuCursor,
+}
+
+/**
+ * Stops the clock the fixed tick runs on, so nothing in the match moves or piles up ticks to
+ * catch up on while paused.
+ */
+fn freeze_time(mut time: ResMut<Time<Virtual>>) {
+    time.pause();
+}
+
+fn unfreeze_time(mut time: ResMut<Time<Virtual>>) {
+    time.unpause();
+}
+
+fn spawn_pause_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let entries = vec![
+        PauseEntry::Resume,
+        PauseEntry::Restart,
+        PauseEntry::Settings,
+        PauseEntry::MainMenu,
+        PauseEntry::Quit,
+    ];
+    let labels: Vec<&str> = entries.iter().map(PauseEntry::label).collect();
+    let font = asset_server.load("fonts/visitor.ttf");
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(16.),
+                    ..default()
+                },
+                background_color: Color::BLACK.with_alpha(0.7).into(),
+                z_index: ZIndex::Global(5),
+                ..default()
+            },
+            StateScoped(GamePhase::Paused),
+        ))
+        .with_children(|builder| {
+            builder.spawn(TextBundle::from_section(
+                "PAUSED",
+                TextStyle {
+                    font_size: 60.,
+                    color: Color::WHITE,
+                    font: font.clone(),
+                },
+            ));
+            spawn_menu_entries(builder, &labels, 0, font);
+        });
+
+    commands.insert_resource(PauseMenu {
+        cursor: MenuCursor::new(entries.len()),
+        entries,
+    });
+}
+
+fn pause_menu_input(
+    input: MenuInput,
+    mut menu: ResMut<PauseMenu>,
+    mut entries: Query<(&mut Text, &MenuEntry)>,
+    mut next_phase: ResMut<NextState<GamePhase>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut next_controls: ResMut<NextState<ControlsScreen>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    for action in input.actions() {
+        if menu.cursor.navigate(action) {
+            highlight_menu_entries(&menu.cursor, &mut entries);
+            continue;
+        }
+        let entry = match action {
+            MenuAction::Back => PauseEntry::Resume,
+            MenuAction::Select => menu.entries[menu.cursor.selected],
+            _ => continue,
+        };
+        match entry {
+            PauseEntry::Resume => next_phase.set(GamePhase::Playing),
+            // leaving and coming back starts the match over with the same players
+            PauseEntry::Restart => next_state.set(GameState::LeaveGame),
+            PauseEntry::Settings => next_controls.set(ControlsScreen::Open),
+            PauseEntry::MainMenu => next_state.set(GameState::MainMenu),
+            PauseEntry::Quit => {
+                exit.send(AppExit::Success);
+            }
+        }
+        return;
+    }
+}
+
+/**
+ * The menu shown while a match is paused: resume, restart the match, change the controls, go
+ * back to the main menu or quit. The match clock is stopped for as long as it shows.
+ */
+pub struct PauseMenuPlugin;
+impl Plugin for PauseMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GamePhase::Paused), (freeze_time, spawn_pause_menu))
+            .add_systems(OnExit(GamePhase::Paused), unfreeze_time)
+            .add_systems(
+                Update,
+                pause_menu_input
+                    .run_if(in_state(GamePhase::Paused))
+                    .run_if(in_state(ControlsScreen::Closed)),
+            );
+    }
+}
@@ -0,0 +1,357 @@
+use std::{
+    net::{SocketAddr, UdpSocket},
+    process,
+    time::Duration,
+};
+
+use bevy::{
+    app::ScheduleRunnerPlugin, log::LogPlugin, prelude::*, state::app::StatesPlugin, utils::HashMap,
+};
+use bevy_asset_loader::prelude::{ConfigureLoadingState, LoadingState, LoadingStateAppExt};
+use bevy_turborand::{prelude::RngPlugin, DelegatedRng, GlobalRng};
+
+use crate::{
+    add_game_states,
+    config::{ProgramConfig, Seed},
+    game::{
+        components::{Apple, Dead, MatchSetup, SnakeHead, TickSet},
+        grid::{Grid, GridPos},
+        level::{LevelAssets, SelectedLevel},
+        prelude::{
+            BodyRef, ControlScheme, DirectionQueue, Named, Player, PlayerBundle, Score,
+            SnakeDirection, SnakeHeadRef, SnakeTextureIndex,
+        },
+        protocol::{self, ClientMessage, ServerMessage, SnakeState, Snapshot},
+        rules::{GameRules, RulesAssets},
+        SimulationPlugin,
+    },
+    GamePhase, GameState,
+};
+
+/// How often the server loop runs. Ticks still happen at the rate of `Time<Fixed>`.
+const SERVER_FRAME_RATE: f64 = 240.;
+const MAX_CLIENTS: usize = 4;
+/// Seconds of silence after which a client loses its seat.
+const CLIENT_TIMEOUT: f32 = 10.;
+/// Seconds the final board stays up before the next match starts.
+const RESTART_DELAY: f32 = 3.;
+
+struct RemoteClient {
+    addr: SocketAddr,
+    name: String,
+    last_heard: f32,
+}
+
+/**
+ * The dedicated server's end of the connections. Each seat is a snake slot that stays with
+ * its client from match to match.
+ */
+#[derive(Resource)]
+struct Server {
+    socket: UdpSocket,
+    seats: [Option<RemoteClient>; MAX_CLIENTS],
+    /// Clients needed before a match starts.
+    players_to_start: usize,
+    match_id: u32,
+    tick: u32,
+    restart_timer: Timer,
+}
+
+impl Server {
+    fn seat_of(&self, addr: SocketAddr) -> Option<usize> {
+        self.seats
+            .iter()
+            .position(|seat| seat.as_ref().is_some_and(|client| client.addr == addr))
+    }
+
+    fn clients(&self) -> impl Iterator<Item = (usize, &RemoteClient)> {
+        self.seats
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, seat)| Some((slot, seat.as_ref()?)))
+    }
+
+    fn send(&self, to: SocketAddr, message: &ServerMessage) {
+        if let Err(err) = protocol::send(&self.socket, to, message) {
+            warn!("could not send to {to}: {err}");
+        }
+    }
+
+    fn broadcast(&self, message: &ServerMessage) {
+        for (_, client) in self.clients() {
+            self.send(client.addr, message);
+        }
+    }
+}
+
+/// The seat a player entity belongs to.
+#[derive(Component)]
+struct ClientSlot(usize);
+
+fn match_start(
+    server: &Server,
+    selected_level: &SelectedLevel,
+    grid: &Grid,
+    rules: &GameRules,
+) -> ServerMessage {
+    ServerMessage::MatchStart {
+        match_id: server.match_id,
+        level: selected_level.0,
+        arena_mode: grid.mode,
+        rules: rules.clone(),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn receive_client_messages(
+    time: Res<Time<Real>>,
+    mut server: ResMut<Server>,
+    state: Res<State<GameState>>,
+    selected_level: Res<SelectedLevel>,
+    grid: Res<Grid>,
+    rules: Res<GameRules>,
+    mut players: Query<(&ClientSlot, &mut DirectionQueue, &SnakeHeadRef)>,
+    heads: Query<&SnakeHead, Without<Dead>>,
+) {
+    let now = time.elapsed_seconds();
+    while let Some((message, from)) = protocol::receive::<ClientMessage>(&server.socket) {
+        let seat = server.seat_of(from);
+        if let Some(client) = seat.and_then(|slot| server.seats[slot].as_mut()) {
+            client.last_heard = now;
+        }
+
+        match (message, seat) {
+            (ClientMessage::Join { name }, seat) => {
+                let free_seat = server.seats.iter().position(Option::is_none);
+                let Some(slot) = seat.or(free_seat) else {
+                    server.send(from, &ServerMessage::Full);
+                    continue;
+                };
+                if seat.is_none() {
+                    info!("{name} joined from {from} in seat {slot}");
+                    server.seats[slot] = Some(RemoteClient {
+                        addr: from,
+                        name,
+                        last_heard: now,
+                    });
+                }
+                server.send(from, &ServerMessage::Welcome { slot });
+                if *state.get() == GameState::InGame {
+                    server.send(from, &match_start(&server, &selected_level, &grid, &rules));
+                }
+            }
+            (ClientMessage::Turn { direction }, Some(slot)) => {
+                let Some(direction) = SnakeDirection::from_char(direction) else {
+                    continue;
+                };
+                for (client_slot, mut queue, head_ref) in players.iter_mut() {
+                    let head = head_ref.0.and_then(|head| heads.get(head).ok());
+                    if let (true, Some(head)) = (client_slot.0 == slot, head) {
+                        queue.push(direction.clone(), &head.direction);
+                    }
+                }
+            }
+            (ClientMessage::Leave, Some(slot)) => {
+                info!("seat {slot} left");
+                server.seats[slot] = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn drop_silent_clients(time: Res<Time<Real>>, mut server: ResMut<Server>) {
+    let now = time.elapsed_seconds();
+    for seat in server.seats.iter_mut() {
+        if seat
+            .as_ref()
+            .is_some_and(|client| now - client.last_heard > CLIENT_TIMEOUT)
+        {
+            info!("{} timed out", seat.as_ref().unwrap().name);
+            *seat = None;
+        }
+    }
+}
+
+fn start_when_ready(server: Res<Server>, mut next_state: ResMut<NextState<GameState>>) {
+    if server.clients().count() >= server.players_to_start {
+        next_state.set(GameState::InGame);
+    }
+}
+
+/**
+ * Seats every connected client at the new match, with a fresh seed drawn from the server's
+ * own seeded generator.
+ */
+fn spawn_client_players(
+    mut commands: Commands,
+    mut server: ResMut<Server>,
+    mut rng: ResMut<GlobalRng>,
+    mut seed: ResMut<Seed>,
+) {
+    server.match_id += 1;
+    server.tick = 0;
+    seed.0 = rng.u64(..);
+    for (slot, client) in server.clients() {
+        commands.spawn((
+            PlayerBundle {
+                player: Player,
+                name: Named(client.name.clone()),
+                control_scheme: ControlScheme {
+                    directional_controls: HashMap::new(),
+                },
+                direction_queue: DirectionQueue::default(),
+                snake_head_ref: SnakeHeadRef(None),
+                score: Score { value: 0 },
+                texture_index: SnakeTextureIndex(slot),
+            },
+            ClientSlot(slot),
+        ));
+    }
+}
+
+fn announce_match(
+    server: Res<Server>,
+    selected_level: Res<SelectedLevel>,
+    grid: Res<Grid>,
+    rules: Res<GameRules>,
+) {
+    info!("match {} started", server.match_id);
+    server.broadcast(&match_start(&server, &selected_level, &grid, &rules));
+}
+
+/**
+ * Sends the board after every tick to every client.
+ */
+#[allow(clippy::too_many_arguments)]
+fn broadcast_snapshot(
+    mut server: ResMut<Server>,
+    phase: Res<State<GamePhase>>,
+    fixed_time: Res<Time<Fixed>>,
+    players: Query<(
+        &ClientSlot,
+        &Named,
+        &Score,
+        &SnakeTextureIndex,
+        &SnakeHeadRef,
+    )>,
+    heads: Query<(&GridPos, &SnakeHead, &BodyRef, Has<Dead>)>,
+    body_parts: Query<&GridPos>,
+    apples: Query<&GridPos, With<Apple>>,
+) {
+    server.tick += 1;
+    let snakes = players
+        .iter()
+        .filter_map(|(slot, name, score, skin, head_ref)| {
+            let (head_pos, head, body_ref, dead) = heads.get(head_ref.0?).ok()?;
+            let body = body_ref
+                .0
+                .iter()
+                .filter_map(|part| body_parts.get(*part).ok());
+            Some(SnakeState {
+                slot: slot.0,
+                name: name.0.clone(),
+                skin: skin.0,
+                cells: std::iter::once(head_pos)
+                    .chain(body)
+                    .map(|pos| pos.0.to_array())
+                    .collect(),
+                direction: head.direction.to_char(),
+                dead,
+                score: score.value,
+            })
+        })
+        .collect();
+
+    server.broadcast(&ServerMessage::Snapshot(Snapshot {
+        match_id: server.match_id,
+        tick: server.tick,
+        tick_length: fixed_time.timestep().as_secs_f32(),
+        snakes,
+        apples: apples.iter().map(|pos| pos.0.to_array()).collect(),
+        over: *phase.get() == GamePhase::Dead,
+    }));
+}
+
+fn restart_after_match(
+    time: Res<Time>,
+    mut server: ResMut<Server>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if server.restart_timer.tick(time.delta()).just_finished() {
+        server.restart_timer.reset();
+        next_state.set(GameState::EnterGame);
+    }
+}
+
+/**
+ * Runs the game as an authoritative server for clients started with `--connect`: it owns the
+ * board and the rules, takes turns from the clients and sends them a snapshot after every tick.
+ * A new match starts whenever enough clients are connected.
+ */
+pub fn run(cfg: &ProgramConfig) {
+    let socket = UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], cfg.port)))
+        .and_then(|socket| socket.set_nonblocking(true).map(|_| socket))
+        .unwrap_or_else(|err| {
+            println!("Could not listen on port {}: {err}", cfg.port);
+            process::exit(1);
+        });
+    println!("Listening on port {}", cfg.port);
+
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+            1. / SERVER_FRAME_RATE,
+        ))),
+        AssetPlugin::default(),
+        StatesPlugin,
+        LogPlugin::default(),
+        RngPlugin::new().with_rng_seed(cfg.seed),
+    ));
+    add_game_states(&mut app)
+        .add_loading_state(
+            LoadingState::new(GameState::AssetLoading)
+                .continue_to_state(GameState::EnterGame)
+                .load_collection::<LevelAssets>()
+                .load_collection::<RulesAssets>(),
+        )
+        .add_plugins(SimulationPlugin)
+        .insert_resource(Grid {
+            mode: cfg.arena_mode,
+            ..default()
+        })
+        .insert_resource(SelectedLevel(cfg.level))
+        .insert_resource(Seed(cfg.seed))
+        .insert_resource(Server {
+            socket,
+            seats: Default::default(),
+            players_to_start: cfg.players.clamp(1, MAX_CLIENTS),
+            match_id: 0,
+            tick: 0,
+            restart_timer: Timer::from_seconds(RESTART_DELAY, TimerMode::Once),
+        })
+        .add_systems(PreUpdate, receive_client_messages)
+        .add_systems(Update, drop_silent_clients)
+        .add_systems(
+            Update,
+            start_when_ready.run_if(in_state(GameState::EnterGame)),
+        )
+        .add_systems(
+            OnEnter(GameState::InGame),
+            (
+                spawn_client_players.before(MatchSetup),
+                announce_match.after(MatchSetup),
+            ),
+        )
+        .add_systems(
+            FixedUpdate,
+            broadcast_snapshot
+                .after(TickSet::Simulation)
+                .run_if(in_state(GameState::InGame)),
+        )
+        .add_systems(
+            Update,
+            restart_after_match.run_if(in_state(GamePhase::Dead)),
+        )
+        .run();
+}
@@ -0,0 +1,86 @@
+use crate::{config::ProgramConfig, game::ai::AiSlots, headless};
+
+#[derive(Default)]
+struct Standing {
+    played: u32,
+    won: u32,
+    drawn: u32,
+}
+
+impl Standing {
+    fn win_rate(&self) -> f32 {
+        if self.played == 0 {
+            return 0.;
+        }
+        self.won as f32 / self.played as f32
+    }
+}
+
+/**
+ * Plays every bot against every other bot headless, `cfg.rounds` matches per pairing, and
+ * prints how often each one won. The bots swap seats every round, and round `n` of every
+ * pairing is played on seed `cfg.seed + n` so all pairings meet the same boards.
+ */
+pub fn run(cfg: &ProgramConfig, bots: &[String]) {
+    let mut standings: Vec<Standing> = bots.iter().map(|_| Standing::default()).collect();
+
+    for a in 0..bots.len() {
+        for b in a + 1..bots.len() {
+            for round in 0..cfg.rounds {
+                let seats = if round % 2 == 0 { [a, b] } else { [b, a] };
+                let slots = AiSlots(seats.iter().map(|bot| Some(bots[*bot].clone())).collect());
+                let seed = cfg.seed.wrapping_add(round as u64);
+                let result = match headless::play_match(cfg, seed, seats.len(), &slots) {
+                    Ok(result) => result,
+                    Err(err) => {
+                        println!(
+                            "{} vs {} (seed {}): not played, {err}",
+                            bots[seats[0]], bots[seats[1]], seed
+                        );
+                        continue;
+                    }
+                };
+
+                let winner = result.winner().map(|slot| seats[slot]);
+                println!(
+                    "{} vs {} (seed {}): {}",
+                    bots[seats[0]],
+                    bots[seats[1]],
+                    seed,
+                    winner.map_or("draw".to_string(), |bot| format!("{} wins", bots[bot]))
+                );
+                for bot in seats {
+                    standings[bot].played += 1;
+                    match winner {
+                        Some(winner) if winner == bot => standings[bot].won += 1,
+                        None => standings[bot].drawn += 1,
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    println!();
+    println!(
+        "{:<16} {:>6} {:>6} {:>6} {:>8}",
+        "bot", "played", "won", "drawn", "win rate"
+    );
+    let mut order: Vec<usize> = (0..bots.len()).collect();
+    order.sort_by(|a, b| {
+        standings[*b]
+            .win_rate()
+            .total_cmp(&standings[*a].win_rate())
+    });
+    for bot in order {
+        let standing = &standings[bot];
+        println!(
+            "{:<16} {:>6} {:>6} {:>6} {:>7.1}%",
+            bots[bot],
+            standing.played,
+            standing.won,
+            standing.drawn,
+            standing.win_rate() * 100.
+        );
+    }
+}
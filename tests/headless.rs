@@ -0,0 +1,30 @@
+use snek::{config::ProgramConfig, game::ai::AiSlots, headless::play_match};
+
+fn bots() -> AiSlots {
+    AiSlots(vec![
+        Some("greedy".to_string()),
+        Some("floodfill".to_string()),
+    ])
+}
+
+#[test]
+fn the_same_seed_plays_the_same_match() {
+    let cfg = ProgramConfig {
+        ticks: 300,
+        ..Default::default()
+    };
+    let first = play_match(&cfg, 4, 2, &bots()).unwrap();
+    let second = play_match(&cfg, 4, 2, &bots()).unwrap();
+
+    assert!(first.ticks > 0);
+    assert_eq!(first.seed, 4);
+    assert_eq!(first.ticks, second.ticks);
+    assert_eq!(first.players.len(), 2);
+    for (a, b) in first.players.iter().zip(&second.players) {
+        assert_eq!(a.name, b.name);
+        assert_eq!(a.score, b.score);
+        assert_eq!(a.length, b.length);
+        assert_eq!(a.dead, b.dead);
+    }
+    assert_eq!(first.winner(), second.winner());
+}